@@ -3,10 +3,24 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use anyhow::{Context, Result, anyhow};
+use const_oid::db::{rfc5912::ECDSA_WITH_SHA_384, rfc8410::ID_ED_25519};
+use der::{
+    DateTime, DecodePem, Encode,
+    asn1::{BitString, GeneralizedTime, UtcTime},
+    pem::LineEnding,
+};
+use ed25519_dalek::{Signer, SigningKey, pkcs8::DecodePrivateKey};
+use p384::ecdsa::DerSignature;
 use std::env;
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::{self, Path, PathBuf};
+use x509_cert::{
+    Certificate, Version,
+    crl::{CertificateList, RevokedCert, TbsCertList},
+    spki::AlgorithmIdentifierOwned,
+    time::Time,
+};
 
 /// Execute one of the `pki-playground` commands to generate part of the PKI
 /// used for testing.
@@ -65,6 +79,111 @@ fn attest_gen_cmd(command: &str, input: &Path, output: &str) -> Result<()> {
     }
 }
 
+/// The key of a CA from the PKI generated by `pki-playground`: ed25519 or
+/// P-384 for the root & intermediate.
+enum CrlKey {
+    Ed25519(SigningKey),
+    P384(p384::ecdsa::SigningKey),
+}
+
+impl CrlKey {
+    fn load(issuer: &str) -> Result<Self> {
+        let key = fs::read_to_string(format!("{issuer}.key.pem"))
+            .with_context(|| format!("read key for {issuer}"))?;
+        match SigningKey::from_pkcs8_pem(&key) {
+            Ok(key) => Ok(Self::Ed25519(key)),
+            Err(_) => p384::ecdsa::SigningKey::from_pkcs8_pem(&key)
+                .map(Self::P384)
+                .map_err(|e| anyhow!("parse key for {issuer}: {e}")),
+        }
+    }
+
+    fn algorithm(&self) -> AlgorithmIdentifierOwned {
+        let oid = match self {
+            Self::Ed25519(_) => ID_ED_25519,
+            Self::P384(_) => ECDSA_WITH_SHA_384,
+        };
+
+        AlgorithmIdentifierOwned {
+            oid,
+            parameters: None,
+        }
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<BitString> {
+        Ok(match self {
+            Self::Ed25519(key) => {
+                BitString::from_bytes(&key.sign(msg).to_bytes())?
+            }
+            Self::P384(key) => {
+                let signature: DerSignature = key.sign(msg);
+                BitString::from_bytes(signature.as_bytes())?
+            }
+        })
+    }
+}
+
+/// The start of `year` as a `Time`, encoded as `UTCTime` if possible.
+fn start_of(year: u16) -> Result<Time> {
+    let time = DateTime::new(year, 1, 1, 0, 0, 0)?;
+    Ok(match year {
+        ..2050 => Time::UtcTime(UtcTime::from_date_time(time)?),
+        _ => Time::GeneralTime(GeneralizedTime::from_date_time(time)),
+    })
+}
+
+/// Issue a CRL signed by the key of the CA `issuer` from the PKI generated
+/// by `pki-playground` that revokes the cert `revoked`, if any. The CRL is
+/// issued at the start of `this_update` & the next one is due at the start
+/// of `next_update`. The `pki-playground` tool doesn't generate CRLs.
+fn issue_crl(
+    issuer: &str,
+    revoked: Option<&str>,
+    this_update: u16,
+    next_update: u16,
+    output: &str,
+) -> Result<()> {
+    let key = CrlKey::load(issuer)?;
+    let issuer = Certificate::from_pem(fs::read(format!("{issuer}.cert.pem"))?)
+        .with_context(|| format!("parse cert for {issuer}"))?;
+    let this_update = start_of(this_update)?;
+    let revoked_certificates = revoked
+        .map(|revoked| {
+            let cert =
+                Certificate::from_pem(fs::read(format!("{revoked}.cert.pem"))?)
+                    .with_context(|| format!("parse cert for {revoked}"))?;
+            Ok::<_, anyhow::Error>(vec![RevokedCert {
+                serial_number: cert.tbs_certificate.serial_number,
+                revocation_date: this_update,
+                crl_entry_extensions: None,
+            }])
+        })
+        .transpose()?;
+
+    let algorithm = key.algorithm();
+    let tbs_cert_list = TbsCertList {
+        version: Version::V2,
+        signature: algorithm.clone(),
+        issuer: issuer.tbs_certificate.subject,
+        this_update,
+        next_update: Some(start_of(next_update)?),
+        revoked_certificates,
+        crl_extensions: None,
+    };
+
+    let signature = key.sign(&tbs_cert_list.to_der()?)?;
+    let crl = CertificateList {
+        tbs_cert_list,
+        signature_algorithm: algorithm,
+        signature,
+    };
+    let pem =
+        der::pem::encode_string("X509 CRL", LineEnding::LF, &crl.to_der()?)
+            .map_err(|e| anyhow!("encode CRL as PEM: {e}"))?;
+
+    fs::write(output, pem).with_context(|| format!("write {output}"))
+}
+
 fn write_path_to_conf(mut file: &File, path: &Path, name: &str) -> Result<()> {
     if !fs::exists(path).with_context(|| {
         format!("checking existance of file: {}", path.display())
@@ -106,6 +225,10 @@ fn main() -> Result<()> {
     write_path_to_conf(&config_out, &out, "ATTESTATION_SIGNER")
         .context("write variable w/ path to attestation signing key")?;
     out.pop();
+    out.push("test-sp-alias.key.pem");
+    write_path_to_conf(&config_out, &out, "SP_ATTESTATION_SIGNER")
+        .context("write variable w/ path to SP attestation signing key")?;
+    out.pop();
 
     // generate certs
     pki_gen_cmd("generate-certificates", &pki_cfg)?;
@@ -121,6 +244,77 @@ fn main() -> Result<()> {
     write_path_to_conf(&config_out, &out, "SIGNER_PKIPATH")
         .context("write variable w/ path to attestation signing key")?;
     out.pop();
+    out.push("test-sp-alias.certlist.pem");
+    write_path_to_conf(&config_out, &out, "SP_SIGNER_PKIPATH")
+        .context("write variable w/ path to SP attestation cert chain")?;
+    out.pop();
+    out.push("test-foreign-alias.certlist.pem");
+    write_path_to_conf(&config_out, &out, "FOREIGN_PKIPATH")
+        .context("write variable w/ path to foreign cert chain")?;
+    out.pop();
+    out.push("test-expired-alias.certlist.pem");
+    write_path_to_conf(&config_out, &out, "EXPIRED_PKIPATH")
+        .context("write variable w/ path to expired cert chain")?;
+    out.pop();
+    out.push("test-future-alias.certlist.pem");
+    write_path_to_conf(&config_out, &out, "NOT_YET_VALID_PKIPATH")
+        .context("write variable w/ path to not yet valid cert chain")?;
+    out.pop();
+    out.push("test-no-policy-alias.certlist.pem");
+    write_path_to_conf(&config_out, &out, "NO_POLICY_PKIPATH")
+        .context("write variable w/ path to cert chain w/o DICE policy")?;
+    out.pop();
+    out.push("test-bad-usage-alias.certlist.pem");
+    write_path_to_conf(&config_out, &out, "BAD_KEY_USAGE_PKIPATH")
+        .context("write variable w/ path to cert chain w/ bad key usage")?;
+    out.pop();
+    out.push("test-no-oana-alias.certlist.pem");
+    write_path_to_conf(&config_out, &out, "NO_PLATFORM_IDENTITY_PKIPATH")
+        .context("write variable w/ path to cert chain w/o OANA policy")?;
+    out.pop();
+    out.push("test-no-identity-alias.certlist.pem");
+    write_path_to_conf(&config_out, &out, "NO_IDENTITY_INIT_PKIPATH")
+        .context("write variable w/ path to cert chain w/o identity policy")?;
+    out.pop();
+
+    // generate a CRL from the device id revoking the platform RoT alias cert
+    issue_crl(
+        "test-device-id",
+        Some("test-alias"),
+        2000,
+        2100,
+        "test-device-id.crl.pem",
+    )?;
+    out.push("test-device-id.crl.pem");
+    write_path_to_conf(&config_out, &out, "CRL")
+        .context("write variable w/ path to CRL")?;
+    out.pop();
+
+    // an earlier CRL from the device id that's no longer current
+    issue_crl(
+        "test-device-id",
+        None,
+        1990,
+        2000,
+        "test-device-id.old.crl.pem",
+    )?;
+    out.push("test-device-id.old.crl.pem");
+    write_path_to_conf(&config_out, &out, "OLD_CRL")
+        .context("write variable w/ path to old CRL")?;
+    out.pop();
+
+    // generate a CRL from the P-384 intermediate revoking the platform id
+    issue_crl(
+        "test-intermediate",
+        Some("test-platform-id"),
+        2000,
+        2100,
+        "test-intermediate.crl.pem",
+    )?;
+    out.push("test-intermediate.crl.pem");
+    write_path_to_conf(&config_out, &out, "INTERMEDIATE_CRL")
+        .context("write variable w/ path to intermediate CRL")?;
+    out.pop();
 
     // generate measurement log
     let mut log_cfg = pki_cfg;
@@ -133,6 +327,16 @@ fn main() -> Result<()> {
         .context("write variable w/ path to attestation signing key")?;
     out.pop();
 
+    // generate measurement log for the mock SP RoT
+    log_cfg.push("sp-log.kdl");
+    attest_gen_cmd("log", &log_cfg, "sp-log.bin")?;
+    log_cfg.pop();
+
+    out.push("sp-log.bin");
+    write_path_to_conf(&config_out, &out, "SP_LOG")
+        .context("write variable w/ path to SP measurement log")?;
+    out.pop();
+
     // generate the corpus of reference measurements
     let mut corim_cfg = log_cfg;
     corim_cfg.push("corim.kdl");
@@ -153,5 +357,11 @@ fn main() -> Result<()> {
         )?;
     vm_instance_cfg.pop();
 
+    let mut instance_policy = vm_instance_cfg;
+    instance_policy.push("instance-policy.kdl");
+    write_path_to_conf(&config_out, &instance_policy, "INSTANCE_POLICY")
+        .context("write variable w/ path to policy for the InstanceRoT log")?;
+    instance_policy.pop();
+
     Ok(())
 }
@@ -0,0 +1,58 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde::Deserialize;
+use std::sync::Arc;
+use vm_attest_trait::{
+    Attestation, CertChain, MeasurementLog, RotType,
+    policy::InstancePolicy,
+    rot::{OxideHardwareVerifier, OxideInstanceVerifier, RotVerifiers},
+};
+
+/// The attestations from each RoT & everything needed to check them.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Input {
+    attestations: Vec<Attestation>,
+    cert_chains: Vec<CertChain>,
+    logs: Vec<MeasurementLog>,
+    /// Hex encoded 32 bytes signed by the RoTs.
+    data: String,
+}
+
+// the attestations from each RoT, checked against the cert chains & logs
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = serde_json::from_slice::<Input>(data) else {
+        return;
+    };
+    let mut signed = [0u8; 32];
+    if hex::decode_to_slice(&input.data, &mut signed).is_err() {
+        return;
+    }
+
+    // the cert chains aren't verified by `verify_attestations` so no trust
+    // anchors are needed
+    let mut rot_verifiers = RotVerifiers::default();
+    for rot in [RotType::OxideHardware, RotType::OxideSp] {
+        rot_verifiers.register(Box::new(OxideHardwareVerifier::new(
+            rot,
+            Arc::default(),
+            Vec::new(),
+        )));
+    }
+    rot_verifiers.register(Box::new(OxideInstanceVerifier::new(
+        Arc::default(),
+        InstancePolicy::default(),
+    )));
+
+    let _ = rot_verifiers.verify_attestations(
+        &input.attestations,
+        &input.cert_chains,
+        &input.logs,
+        &signed,
+    );
+});
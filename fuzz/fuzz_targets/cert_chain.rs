@@ -0,0 +1,22 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use vm_attest_trait::fuzzing;
+use x509_cert::{Certificate, PkiPath, der::Decode};
+
+// a cert chain encoded as either a list of PEM certs or a DER SEQUENCE of
+// certs, and the DICE TcbInfo extension in each cert
+fuzz_target!(|data: &[u8]| {
+    let mut pki_path = Certificate::load_pem_chain(data).unwrap_or_default();
+    if let Ok(der) = PkiPath::from_der(data) {
+        pki_path.extend(der);
+    }
+
+    for cert in &pki_path {
+        let _ = fuzzing::cert_fwids(cert);
+    }
+});
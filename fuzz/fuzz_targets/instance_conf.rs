@@ -0,0 +1,13 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use vm_attest_trait::{MeasurementLog, RotType};
+
+// the JSON encoded VmInstanceConf from propolis
+fuzz_target!(|data: &[u8]| {
+    let _ = MeasurementLog::new(RotType::OxideInstance, data.to_vec()).decode();
+});
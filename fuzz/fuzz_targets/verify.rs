@@ -0,0 +1,78 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde::Deserialize;
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use vm_attest_trait::{
+    Nonce, RotType,
+    nonce_store::{MemoryNonceStore, NonceStore},
+    policy::InstancePolicy,
+    rot::{OxideHardwareVerifier, OxideInstanceVerifier, RotVerifiers},
+    trust_store::TrustStore,
+    verifier::{Evidence, Verifier},
+};
+use x509_cert::{Certificate, der::DecodePem};
+
+/// An evidence bundle & everything else the verifier needs to check it.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Input {
+    /// PEM encoded root of the platform PKI.
+    trust_anchor: String,
+    /// Hex encoded nonce the evidence was produced for.
+    nonce: String,
+    /// Hex encoded user data the evidence was produced for.
+    user_data: String,
+    evidence: Evidence,
+}
+
+// the full verification pipeline from an evidence bundle
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = serde_json::from_slice::<Input>(data) else {
+        return;
+    };
+    let Ok(trust_anchor) = Certificate::from_pem(&input.trust_anchor) else {
+        return;
+    };
+    let mut nonce = [0u8; 32];
+    if hex::decode_to_slice(&input.nonce, &mut nonce).is_err() {
+        return;
+    }
+    let nonce = Nonce::from_array(nonce);
+    let Ok(user_data) = hex::decode(&input.user_data) else {
+        return;
+    };
+
+    let trust_store = Arc::new(TrustStore::from(vec![trust_anchor]));
+    let mut rot_verifiers = RotVerifiers::default();
+    for rot in [RotType::OxideHardware, RotType::OxideSp] {
+        rot_verifiers.register(Box::new(OxideHardwareVerifier::new(
+            rot,
+            trust_store.clone(),
+            Vec::new(),
+        )));
+    }
+    rot_verifiers.register(Box::new(OxideInstanceVerifier::new(
+        trust_store,
+        InstancePolicy::default(),
+    )));
+
+    let nonce_store = MemoryNonceStore::default();
+    nonce_store
+        .insert(&nonce, SystemTime::now())
+        .expect("insert nonce");
+    let verifier = Verifier::new(
+        rot_verifiers,
+        Duration::from_secs(60),
+        Box::new(nonce_store),
+    );
+
+    let _ = verifier.verify(&input.evidence, &nonce, &user_data);
+});
@@ -0,0 +1,560 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{
+    LogDecodeError, Measurement, RotType, platform_identity::PlatformIdentity,
+    policy::RuleResult, rot::VerifiedChain,
+};
+use const_oid::{
+    ObjectIdentifier,
+    db::rfc5912::{ID_SHA_256, ID_SHA_384, ID_SHA_512},
+};
+use der::{
+    Decode, Sequence,
+    asn1::{BitString, Int, OctetString},
+};
+use rats_corim::{ConciseTagTypeChoice, Corim, MeasuredElementTypeChoice};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+use x509_cert::Certificate;
+
+/// OID for the DICE TcbInfo extension from the TCG DICE Attestation
+/// Architecture spec.
+const TCG_DICE_TCB_INFO: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("2.23.133.5.4.1");
+
+// const-oid doesn't have names for the sha3 family
+const ID_SHA3_256: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.8");
+const ID_SHA3_384: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.9");
+const ID_SHA3_512: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.10");
+
+/// Errors produced while gathering measurements for appraisal. These
+/// indicate malformed evidence, not a failed appraisal: measurements that
+/// don't match a reference are reported in the `AppraisalReport`.
+#[derive(Debug, thiserror::Error)]
+pub enum AppraisalError {
+    #[error("no RotVerifier registered for {0:?}")]
+    UnsupportedRot(RotType),
+    #[error("no cert chain for {0:?}")]
+    NoCertChain(RotType),
+    #[error("measurement log decoded for the wrong RoT, expected {0:?}")]
+    UnexpectedLog(RotType),
+    #[error("no measurement log for {0:?}")]
+    NoMeasurementLog(RotType),
+    #[error("error decoding measurement log")]
+    LogDecode(#[from] LogDecodeError),
+    #[error("error decoding DICE TcbInfo extension")]
+    TcbInfo(#[from] der::Error),
+    #[error("unsupported digest algorithm in DICE FWID: {0}")]
+    FwidAlgorithm(ObjectIdentifier),
+    #[error("unsupported digest algorithm in reference manifest: {0}")]
+    CorimAlgorithm(i64),
+}
+
+/// The reference measurements from a single CoMID tag in a CoRIM,
+/// identified by its tag-id. Each measurement is keyed by the mkey of the
+/// claim it came from, see `MeasurementSource::mkey`. References w/o an mkey
+/// aren't bound to a RoT & match a measurement from any source.
+#[derive(Clone, Debug)]
+pub struct ReferenceManifest {
+    pub tag_id: String,
+    pub measurements: BTreeMap<Option<String>, BTreeSet<Measurement>>,
+}
+
+impl ReferenceManifest {
+    /// Get one `ReferenceManifest` for each CoMID tag in `corim`.
+    pub fn from_corim(corim: &Corim) -> Result<Vec<Self>, AppraisalError> {
+        let mut manifests = Vec::new();
+
+        for tag in &corim.tags {
+            let comid = match tag {
+                ConciseTagTypeChoice::Mid(comid) => comid,
+                _ => continue,
+            };
+            let mut manifest = Self {
+                tag_id: comid.tag_identity.tag_id.to_string(),
+                measurements: BTreeMap::new(),
+            };
+
+            let triples = comid.triples.reference_triples.iter().flatten();
+            for claim in triples.flat_map(|t| t.ref_claims.iter()) {
+                let mkey = match &claim.mkey {
+                    Some(MeasuredElementTypeChoice::Tstr(mkey)) => {
+                        Some(mkey.clone())
+                    }
+                    // not bound to any `MeasurementSource`
+                    Some(_) => continue,
+                    None => None,
+                };
+                let digests = match &claim.mval.digests {
+                    Some(digests) => digests,
+                    None => continue,
+                };
+                for digest in digests {
+                    let algorithm = named_info_algorithm(digest.alg)
+                        .ok_or(AppraisalError::CorimAlgorithm(digest.alg))?;
+                    manifest
+                        .measurements
+                        .entry(mkey.clone())
+                        .or_default()
+                        .insert(Measurement {
+                            algorithm: algorithm.to_string(),
+                            digest: hex::encode(&digest.val),
+                        });
+                }
+            }
+
+            manifests.push(manifest);
+        }
+
+        Ok(manifests)
+    }
+
+    /// Returns `true` if this manifest has `measurement` as a reference for
+    /// `source`.
+    pub fn contains(
+        &self,
+        source: &MeasurementSource,
+        measurement: &Measurement,
+    ) -> bool {
+        [None, source.mkey()].iter().any(|mkey| {
+            self.measurements
+                .get(mkey)
+                .is_some_and(|references| references.contains(measurement))
+        })
+    }
+}
+
+/// Map a hash algorithm identifier from the IANA "Named Information Hash
+/// Algorithm Registry" (used by CoRIM) to the names used in this crate.
+fn named_info_algorithm(alg: i64) -> Option<&'static str> {
+    match alg {
+        1 => Some("sha-256"),
+        7 => Some("sha-384"),
+        8 => Some("sha-512"),
+        10 => Some("sha3-256"),
+        11 => Some("sha3-384"),
+        12 => Some("sha3-512"),
+        _ => None,
+    }
+}
+
+/// Map the OID from a DICE FWID to the names used in this crate.
+fn fwid_algorithm(oid: &ObjectIdentifier) -> Option<&'static str> {
+    match *oid {
+        ID_SHA_256 => Some("sha-256"),
+        ID_SHA_384 => Some("sha-384"),
+        ID_SHA_512 => Some("sha-512"),
+        ID_SHA3_256 => Some("sha3-256"),
+        ID_SHA3_384 => Some("sha3-384"),
+        ID_SHA3_512 => Some("sha3-512"),
+        _ => None,
+    }
+}
+
+impl From<&attest_data::Measurement> for Measurement {
+    fn from(measurement: &attest_data::Measurement) -> Self {
+        match measurement {
+            attest_data::Measurement::Sha3_256(digest) => Self {
+                algorithm: "sha3-256".to_string(),
+                digest: hex::encode(digest.0),
+            },
+        }
+    }
+}
+
+/// FWID ::= SEQUENCE {
+///     hashAlg OBJECT IDENTIFIER,
+///     digest OCTET STRING
+/// }
+#[derive(Sequence)]
+struct Fwid {
+    hash_alg: ObjectIdentifier,
+    digest: OctetString,
+}
+
+/// The DiceTcbInfo structure from the TCG DICE Attestation Architecture. We
+/// only care about the `fwids` but must describe the fields before it.
+#[derive(Sequence)]
+struct DiceTcbInfo {
+    #[asn1(context_specific = "0", tag_mode = "IMPLICIT", optional = "true")]
+    vendor: Option<String>,
+    #[asn1(context_specific = "1", tag_mode = "IMPLICIT", optional = "true")]
+    model: Option<String>,
+    #[asn1(context_specific = "2", tag_mode = "IMPLICIT", optional = "true")]
+    version: Option<String>,
+    #[asn1(context_specific = "3", tag_mode = "IMPLICIT", optional = "true")]
+    svn: Option<Int>,
+    #[asn1(context_specific = "4", tag_mode = "IMPLICIT", optional = "true")]
+    layer: Option<Int>,
+    #[asn1(context_specific = "5", tag_mode = "IMPLICIT", optional = "true")]
+    index: Option<Int>,
+    #[asn1(context_specific = "6", tag_mode = "IMPLICIT", optional = "true")]
+    fwids: Option<Vec<Fwid>>,
+    #[asn1(context_specific = "7", tag_mode = "IMPLICIT", optional = "true")]
+    flags: Option<BitString>,
+    #[asn1(context_specific = "8", tag_mode = "IMPLICIT", optional = "true")]
+    vendor_info: Option<OctetString>,
+    #[asn1(context_specific = "9", tag_mode = "IMPLICIT", optional = "true")]
+    tcb_type: Option<OctetString>,
+}
+
+/// Get the FWIDs from the DICE TcbInfo extension in the provided cert.
+pub(crate) fn cert_fwids(
+    cert: &Certificate,
+) -> Result<Vec<Measurement>, AppraisalError> {
+    let extensions = match &cert.tbs_certificate.extensions {
+        Some(extensions) => extensions,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut measurements = Vec::new();
+    for ext in extensions.iter().filter(|e| e.extn_id == TCG_DICE_TCB_INFO) {
+        let tcb_info = DiceTcbInfo::from_der(ext.extn_value.as_bytes())?;
+        for fwid in tcb_info.fwids.unwrap_or_default() {
+            let algorithm = fwid_algorithm(&fwid.hash_alg)
+                .ok_or(AppraisalError::FwidAlgorithm(fwid.hash_alg))?;
+            measurements.push(Measurement {
+                algorithm: algorithm.to_string(),
+                digest: hex::encode(fwid.digest.as_bytes()),
+            });
+        }
+    }
+
+    Ok(measurements)
+}
+
+/// Where a measurement in the `AppraisalReport` came from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MeasurementSource {
+    /// FWID from the DICE TcbInfo extension of the cert at `index` in the
+    /// cert chain for `rot`
+    CertChain { rot: RotType, index: usize },
+    /// The entry at `index` in the measurement log from `rot`
+    Log { rot: RotType, index: usize },
+}
+
+impl MeasurementSource {
+    /// The mkey of the reference measurements that apply to measurements
+    /// from this source, see test-data/corim.kdl. Only the hardware RoTs are
+    /// appraised against reference manifests.
+    pub fn mkey(&self) -> Option<String> {
+        let (rot, source) = match self {
+            Self::CertChain { rot, .. } => (rot, "cert-chain"),
+            Self::Log { rot, .. } => (rot, "log"),
+        };
+        let prefix = match rot {
+            RotType::OxideHardware => "",
+            RotType::OxideSp => "sp-",
+            _ => return None,
+        };
+
+        Some(format!("{prefix}fwid-from-{source}"))
+    }
+}
+
+impl fmt::Display for MeasurementSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CertChain { rot, index } => {
+                write!(f, "{rot:?} cert chain[{index}]")
+            }
+            Self::Log { rot, index } => write!(f, "{rot:?} log[{index}]"),
+        }
+    }
+}
+
+/// The result of appraising a single measurement.
+#[derive(Debug, Clone, Serialize)]
+pub struct MeasurementAppraisal {
+    pub source: MeasurementSource,
+    pub measurement: Measurement,
+    /// The tag-id of each reference manifest that contains `measurement`.
+    /// If this is empty the measurement is unknown to the appraiser.
+    pub matched_by: Vec<String>,
+}
+
+impl MeasurementAppraisal {
+    pub fn is_match(&self) -> bool {
+        !self.matched_by.is_empty()
+    }
+}
+
+/// A description of every measurement considered in the appraisal process,
+/// where it came from, and the reference it was matched against (if any).
+/// Appraisal succeeds only if every measurement was matched.
+#[derive(Debug, Default, Serialize)]
+pub struct AppraisalReport {
+    pub measurements: Vec<MeasurementAppraisal>,
+    /// The result of each rule from the `InstancePolicy` evaluated against
+    /// the `VmInstanceConf` from the `RotType::OxideInstance` log.
+    pub instance: Vec<RuleResult>,
+    /// RoTs whose measurement log had nothing to appraise it against, e.g.
+    /// the `RotType::OxideInstance` w/ an empty `InstancePolicy`.
+    pub unappraised: Vec<RotType>,
+    /// The trust anchor that validated each cert chain & the identity of
+    /// the platform that produced it. This is only populated by
+    /// `Verifier::verify`, appraisal doesn't verify cert chains.
+    pub cert_chains: Vec<VerifiedChain>,
+}
+
+impl AppraisalReport {
+    /// Returns `true` if all measurements matched a reference, all instance
+    /// policy rules passed & every log was appraised. A report w/ nothing in
+    /// it isn't ok.
+    pub fn is_ok(&self) -> bool {
+        !(self.measurements.is_empty() && self.instance.is_empty())
+            && self.unappraised.is_empty()
+            && self.measurements.iter().all(|m| m.is_match())
+            && self.instance.iter().all(|r| r.passed)
+    }
+
+    /// Measurements not found in any of the reference manifests.
+    pub fn unmatched(&self) -> impl Iterator<Item = &MeasurementAppraisal> {
+        self.measurements.iter().filter(|m| !m.is_match())
+    }
+
+    /// Instance policy rules that failed.
+    pub fn failed_rules(&self) -> impl Iterator<Item = &RuleResult> {
+        self.instance.iter().filter(|r| !r.passed)
+    }
+
+    /// The identity of the platform that produced the evidence, from the
+    /// first verified cert chain that identifies it.
+    pub fn platform(&self) -> Option<&PlatformIdentity> {
+        self.cert_chains.iter().find_map(|c| c.platform.as_ref())
+    }
+}
+
+impl fmt::Display for AppraisalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chain in &self.cert_chains {
+            writeln!(f, "{chain}")?;
+        }
+        for m in &self.measurements {
+            let result = if m.is_match() {
+                format!("matched: {}", m.matched_by.join(", "))
+            } else {
+                "UNMATCHED".to_string()
+            };
+            writeln!(f, "{}: {} {}", m.source, m.measurement, result)?;
+        }
+        for result in &self.instance {
+            writeln!(f, "instance {result}")?;
+        }
+        for rot in &self.unappraised {
+            writeln!(f, "{rot:?} log: UNAPPRAISED")?;
+        }
+        write!(f, "result: {}", if self.is_ok() { "PASS" } else { "FAIL" })
+    }
+}
+
+/// Look up `measurement` in each of the reference manifests.
+pub(crate) fn appraise_measurement(
+    source: MeasurementSource,
+    measurement: Measurement,
+    manifests: &[ReferenceManifest],
+) -> MeasurementAppraisal {
+    let matched_by = manifests
+        .iter()
+        .filter(|m| m.contains(&source, &measurement))
+        .map(|m| m.tag_id.clone())
+        .collect();
+
+    MeasurementAppraisal {
+        source,
+        measurement,
+        matched_by,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        AttestationSigner, CertChain, MeasurementLog, VmInstanceConf,
+        policy::InstancePolicy,
+        rot::{OxideHardwareVerifier, OxideInstanceVerifier, RotVerifiers},
+        test::{config, setup},
+    };
+    use std::{fs, sync::Arc};
+
+    /// Appraise w/ the default `RotVerifier`s. There are no trust anchors
+    /// since cert chains aren't verified by `RotVerifiers::appraise`.
+    fn appraise(
+        cert_chains: &[CertChain],
+        logs: &[MeasurementLog],
+        manifests: Vec<ReferenceManifest>,
+        instance_policy: InstancePolicy,
+    ) -> Result<AppraisalReport, AppraisalError> {
+        let mut verifiers = RotVerifiers::default();
+        for rot in [RotType::OxideHardware, RotType::OxideSp] {
+            verifiers.register(Box::new(OxideHardwareVerifier::new(
+                rot,
+                Arc::default(),
+                manifests.clone(),
+            )));
+        }
+        verifiers.register(Box::new(OxideInstanceVerifier::new(
+            Arc::default(),
+            instance_policy,
+        )));
+
+        verifiers.appraise(cert_chains, logs)
+    }
+
+    fn manifests() -> Vec<ReferenceManifest> {
+        let corim = Corim::from_file(config::CORIM).expect("Corim from file");
+        ReferenceManifest::from_corim(&corim)
+            .expect("ReferenceManifest from Corim")
+    }
+
+    fn instance_reference() -> VmInstanceConf {
+        let instance_rim = fs::read_to_string(config::VM_INSTANCE_CFG)
+            .expect("read VM_INSTANCE_CFG to string");
+        serde_json::from_str(&instance_rim)
+            .expect("parse JSON from rim for instance RoT log")
+    }
+
+    fn instance_policy() -> InstancePolicy {
+        InstancePolicy::from_file(config::INSTANCE_POLICY)
+            .expect("InstancePolicy from file")
+    }
+
+    #[test]
+    fn report_all_matched() {
+        let attest = setup();
+        let cert_chains = attest.get_cert_chains().expect("get_cert_chains");
+        let logs = attest.get_measurement_logs().expect("get_measurement_logs");
+
+        let instance_policy = instance_policy();
+        let report =
+            appraise(&cert_chains, &logs, manifests(), instance_policy.clone())
+                .expect("appraise");
+        assert!(report.is_ok(), "{report}");
+        assert_eq!(report.instance.len(), instance_policy.rules.len());
+
+        // for each hardware RoT: one FWID from the alias cert & one
+        // measurement from the log, see test-data/config.kdl,
+        // test-data/log.kdl & test-data/sp-log.kdl
+        assert_eq!(report.measurements.len(), 4);
+        assert!(matches!(
+            report.measurements[0].source,
+            MeasurementSource::CertChain {
+                rot: RotType::OxideHardware,
+                index: 0
+            }
+        ));
+        assert!(matches!(
+            report.measurements[2].source,
+            MeasurementSource::CertChain {
+                rot: RotType::OxideSp,
+                index: 0
+            }
+        ));
+        for measurement in &report.measurements {
+            // tag-id from test-data/corim.kdl
+            assert_eq!(measurement.matched_by, vec!["test".to_string()]);
+        }
+    }
+
+    #[test]
+    fn report_unmatched() {
+        let attest = setup();
+        let cert_chains = attest.get_cert_chains().expect("get_cert_chains");
+        let logs = attest.get_measurement_logs().expect("get_measurement_logs");
+
+        let mut instance_reference = instance_reference();
+        instance_reference.uuid = uuid::Uuid::nil();
+        let instance_policy = InstancePolicy::from(&instance_reference);
+
+        // no reference manifests: every measurement is unmatched
+        let report = appraise(&cert_chains, &logs, Vec::new(), instance_policy)
+            .expect("appraise");
+        assert!(!report.is_ok());
+        assert_eq!(report.unmatched().count(), report.measurements.len());
+
+        let failed: Vec<_> = report.failed_rules().collect();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].rule.field, "uuid");
+    }
+
+    #[test]
+    fn report_mkey() {
+        let measurement = Measurement {
+            algorithm: "sha3-256".to_string(),
+            digest: "4fd56b024c4b73c4be53ec8e7b4dd1492301bb929eddca427a7a0bca4fbe98a8"
+                .to_string(),
+        };
+        let manifests = [ReferenceManifest {
+            tag_id: "test".to_string(),
+            measurements: BTreeMap::from([(
+                Some("sp-fwid-from-cert-chain".to_string()),
+                BTreeSet::from([measurement.clone()]),
+            )]),
+        }];
+
+        // an SP RoT FWID doesn't match in the platform RoT cert chain
+        for (rot, matched) in
+            [(RotType::OxideSp, true), (RotType::OxideHardware, false)]
+        {
+            let source = MeasurementSource::CertChain { rot, index: 0 };
+            let appraisal =
+                appraise_measurement(source, measurement.clone(), &manifests);
+            assert_eq!(appraisal.is_match(), matched);
+        }
+
+        // nor in the SP RoT log
+        let source = MeasurementSource::Log {
+            rot: RotType::OxideSp,
+            index: 0,
+        };
+        assert!(
+            !appraise_measurement(source, measurement, &manifests).is_match()
+        );
+    }
+
+    #[test]
+    fn report_incomplete() {
+        let attest = setup();
+        let cert_chains = attest.get_cert_chains().expect("get_cert_chains");
+        let logs = attest.get_measurement_logs().expect("get_measurement_logs");
+
+        // an empty report isn't ok
+        assert!(!AppraisalReport::default().is_ok());
+
+        // every registered RoT must provide a log
+        let mut hardware_logs =
+            attest.get_measurement_logs().expect("get_measurement_logs");
+        hardware_logs.retain(|l| l.rot != RotType::OxideInstance);
+        let result = appraise(
+            &cert_chains,
+            &hardware_logs,
+            manifests(),
+            instance_policy(),
+        );
+        assert!(matches!(
+            result,
+            Err(AppraisalError::NoMeasurementLog(RotType::OxideInstance))
+        ));
+
+        // the instance log isn't appraised by an empty policy
+        let report = appraise(
+            &cert_chains,
+            &logs,
+            manifests(),
+            InstancePolicy::default(),
+        )
+        .expect("appraise");
+        assert!(!report.is_ok(), "{report}");
+        assert_eq!(report.unappraised, vec![RotType::OxideInstance]);
+    }
+}
@@ -0,0 +1,650 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use anyhow::{Context, Result, anyhow};
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use std::{
+    fmt, fs,
+    io::{self, BufReader, Write},
+    net::TcpListener,
+    os::unix::net::UnixListener,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::SystemTime,
+};
+use vm_attest_trait::{
+    AttestationSigner, CertChain, MeasurementLog, Nonce, RotType,
+    appraise::AppraisalReport,
+    diff::EvidenceDiff,
+    inspect::{Inspection, LogInspection},
+    protocol::{self, Client, Endpoint},
+    reference::{DigestAlgorithm, ReferenceValues},
+    rot::VerifiedChain,
+    verifier::{
+        Evidence, EvidenceBundle, NonceStoreConfig, Verifier, VerifierConfig,
+    },
+};
+use x509_cert::{
+    Certificate,
+    der::{EncodePem, pem::LineEnding},
+};
+
+mod mock;
+
+use mock::MockConfig;
+
+/// Request evidence from an `AttestationSigner`.
+#[derive(Parser)]
+#[command(name = "vm-attest", version)]
+struct Cli {
+    #[command(flatten)]
+    signer: SignerArgs,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Where to find the `AttestationSigner`.
+#[derive(Args)]
+#[group(multiple = false)]
+struct SignerArgs {
+    /// Endpoint serving the signer protocol: unix:PATH or tcp:ADDR
+    #[arg(long)]
+    endpoint: Option<Endpoint>,
+
+    /// JSON config for an in-process `AttestMock`
+    #[arg(long)]
+    mock: Option<PathBuf>,
+}
+
+/// The nonce & user data the attestations are bound to.
+#[derive(Args)]
+struct ChallengeArgs {
+    /// Hex encoded 32 byte nonce, a random nonce is used if omitted
+    #[arg(long, conflicts_with = "nonce_file")]
+    nonce: Option<String>,
+
+    /// File holding the raw 32 byte nonce
+    #[arg(long)]
+    nonce_file: Option<PathBuf>,
+
+    /// Hex encoded user data
+    #[arg(long, conflicts_with = "user_data_file")]
+    user_data: Option<String>,
+
+    /// File holding the raw user data
+    #[arg(long)]
+    user_data_file: Option<PathBuf>,
+}
+
+impl ChallengeArgs {
+    fn nonce(&self) -> Result<Nonce> {
+        let nonce = match (&self.nonce, &self.nonce_file) {
+            (Some(hex), _) => hex::decode(hex).context("decode nonce")?,
+            (_, Some(path)) => fs::read(path)
+                .with_context(|| format!("read {}", path.display()))?,
+            (None, None) => {
+                return Nonce::from_platform_rng()
+                    .map_err(|e| anyhow!("generate nonce: {e}"));
+            }
+        };
+        let nonce: [u8; 32] = nonce.try_into().map_err(|n: Vec<u8>| {
+            anyhow!("nonce must be 32 bytes, got {}", n.len())
+        })?;
+
+        Ok(Nonce::from_array(nonce))
+    }
+
+    fn user_data(&self) -> Result<Vec<u8>> {
+        match (&self.user_data, &self.user_data_file) {
+            (Some(hex), _) => hex::decode(hex).context("decode user data"),
+            (_, Some(path)) => fs::read(path)
+                .with_context(|| format!("read {}", path.display())),
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+}
+
+/// Inputs for verifying a saved evidence bundle.
+#[derive(Args)]
+struct OfflineArgs {
+    /// JSON evidence bundle written by the `evidence` subcommand
+    bundle: PathBuf,
+
+    /// DER or PEM encoded root cert for the platform PKI, or a directory of
+    /// them w/ staged roots in the `staged` subdirectory
+    #[arg(long, required = true)]
+    root: Vec<PathBuf>,
+
+    /// DER or PEM encoded CRL from a CA in the platform PKI
+    #[arg(long)]
+    crl: Vec<PathBuf>,
+
+    /// Check cert validity at this RFC 3339 time (e.g. when the evidence
+    /// was collected) in place of the current time
+    #[arg(long)]
+    at: Option<der::DateTime>,
+
+    /// Print the result as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+fn read_bundle(path: &Path) -> Result<EvidenceBundle> {
+    let bundle = fs::read_to_string(path)
+        .with_context(|| format!("read {}", path.display()))?;
+    serde_json::from_str(&bundle)
+        .with_context(|| format!("parse {}", path.display()))
+}
+
+/// The logs & cert chains to inspect. The signer is queried if none of the
+/// files are provided.
+#[derive(Args)]
+struct InspectArgs {
+    /// JSON evidence bundle written by the `evidence` subcommand
+    #[arg(long, conflicts_with_all = ["log", "cert_chain"])]
+    bundle: Option<PathBuf>,
+
+    /// Measurement log as produced by the RoT from `--rot`
+    #[arg(long)]
+    log: Option<PathBuf>,
+
+    /// PEM encoded cert chain, leaf first
+    #[arg(long)]
+    cert_chain: Option<PathBuf>,
+
+    /// The RoT that produced `--log` & `--cert-chain`
+    #[arg(long, default_value = "oxide-hardware")]
+    rot: RotType,
+
+    /// Print the description as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+impl InspectArgs {
+    fn is_offline(&self) -> bool {
+        self.bundle.is_some() || self.log.is_some() || self.cert_chain.is_some()
+    }
+
+    /// Load the logs & cert chains from the files provided.
+    fn load(&self) -> Result<(Vec<MeasurementLog>, Vec<CertChain>)> {
+        if let Some(path) = &self.bundle {
+            let bundle = read_bundle(path)?;
+            return Ok((bundle.evidence.logs, bundle.evidence.cert_chains));
+        }
+
+        let mut logs = Vec::new();
+        if let Some(path) = &self.log {
+            let data = fs::read(path)
+                .with_context(|| format!("read {}", path.display()))?;
+            logs.push(MeasurementLog::new(self.rot, data));
+        }
+
+        let mut cert_chains = Vec::new();
+        if let Some(path) = &self.cert_chain {
+            let pem = fs::read(path)
+                .with_context(|| format!("read {}", path.display()))?;
+            let pki_path = Certificate::load_pem_chain(&pem)
+                .with_context(|| format!("parse {}", path.display()))?;
+            cert_chains.push(CertChain::new(self.rot, pki_path));
+        }
+
+        Ok((logs, cert_chains))
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Request attestations & print them as JSON
+    Attest {
+        #[command(flatten)]
+        challenge: ChallengeArgs,
+    },
+    /// Print the measurement logs
+    Logs {
+        /// Print the logs as they're returned by the signer instead of
+        /// decoding them
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Print the PEM encoded cert chain for each RoT
+    CertChains,
+    /// Describe the measurement logs & the certs in each cert chain
+    Inspect {
+        #[command(flatten)]
+        inspect: InspectArgs,
+    },
+    /// Collect the evidence for a nonce & user data & write it to a file
+    Evidence {
+        #[command(flatten)]
+        challenge: ChallengeArgs,
+
+        /// File to write the JSON evidence bundle to
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Verify the cert chains & attestations from a saved evidence bundle
+    Verify {
+        #[command(flatten)]
+        offline: OfflineArgs,
+    },
+    /// Verify a saved evidence bundle & appraise its measurements
+    Appraise {
+        #[command(flatten)]
+        offline: OfflineArgs,
+
+        /// CBOR encoded CoRIM w/ reference measurements
+        #[arg(long, required = true)]
+        corim: Vec<PathBuf>,
+
+        /// KDL policy for the VM instance config
+        #[arg(long)]
+        instance_policy: PathBuf,
+    },
+    /// Compare two saved evidence bundles
+    Diff {
+        /// JSON evidence bundle from a known good VM
+        left: PathBuf,
+
+        /// JSON evidence bundle to compare w/ `left`
+        right: PathBuf,
+
+        /// Print the differences as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Generate the reference values for a raw disk image
+    Reference {
+        /// The raw disk image
+        image: PathBuf,
+
+        /// Digest algorithm: sha-256, sha-384, sha-512, sha3-256, sha3-384
+        /// or sha3-512
+        #[arg(long, default_value = "sha-256")]
+        algorithm: DigestAlgorithm,
+
+        /// File to write the instance reference JSON to, stdout if omitted
+        #[arg(long)]
+        instance_out: Option<PathBuf>,
+
+        /// File to write the CoRIM KDL to
+        #[arg(long)]
+        corim_out: Option<PathBuf>,
+
+        /// Vendor for the CoRIM
+        #[arg(long, default_value = "oxide")]
+        vendor: String,
+
+        /// Tag id for the CoRIM, the image file name if omitted
+        #[arg(long)]
+        tag_id: Option<String>,
+
+        /// File to write the KDL instance policy to
+        #[arg(long)]
+        policy_out: Option<PathBuf>,
+    },
+    /// Serve the signer protocol w/ the `AttestMock` from `--mock`
+    MockDaemon {
+        /// Endpoint to listen on: unix:PATH or tcp:ADDR
+        #[arg(long)]
+        listen: Endpoint,
+    },
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, value)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+/// Run `command` against `signer`.
+fn run<S>(signer: &S, command: Command) -> Result<()>
+where
+    S: AttestationSigner,
+    S::Error: Send + Sync,
+{
+    match command {
+        Command::Attest { challenge } => {
+            let attestations =
+                signer.attest(&challenge.nonce()?, &challenge.user_data()?)?;
+            print_json(&attestations)
+        }
+        Command::Logs { raw: true } => {
+            print_json(&signer.get_measurement_logs()?)
+        }
+        Command::Logs { raw: false } => {
+            for log in signer.get_measurement_logs()? {
+                print!("{}", LogInspection::new(&log)?);
+            }
+            Ok(())
+        }
+        Command::CertChains => {
+            for cert_chain in signer.get_cert_chains()? {
+                println!("# {}", cert_chain.rot());
+                for cert in cert_chain.pki_path() {
+                    print!("{}", cert.to_pem(LineEnding::LF)?);
+                }
+            }
+            Ok(())
+        }
+        Command::Inspect { inspect: args } => inspect(
+            &args,
+            &signer.get_measurement_logs()?,
+            &signer.get_cert_chains()?,
+        ),
+        Command::Evidence { challenge, out } => {
+            let nonce = challenge.nonce()?;
+            let user_data = challenge.user_data()?;
+            let evidence = Evidence::collect(signer, &nonce, &user_data)?;
+            let bundle = EvidenceBundle {
+                nonce,
+                user_data,
+                evidence,
+            };
+            let json = serde_json::to_vec_pretty(&bundle)?;
+            fs::write(&out, json)
+                .with_context(|| format!("write {}", out.display()))
+        }
+        Command::Verify { .. }
+        | Command::Appraise { .. }
+        | Command::Diff { .. }
+        | Command::Reference { .. } => {
+            unreachable!("offline commands don't use a signer")
+        }
+        Command::MockDaemon { .. } => {
+            Err(anyhow!("mock-daemon requires --mock"))
+        }
+    }
+}
+
+/// Describe `logs` & `cert_chains` for a human or as JSON.
+fn inspect(
+    args: &InspectArgs,
+    logs: &[MeasurementLog],
+    cert_chains: &[CertChain],
+) -> Result<()> {
+    let inspection = Inspection::new(logs, cert_chains)?;
+    match args.json {
+        true => print_json(&inspection),
+        false => {
+            print!("{inspection}");
+            Ok(())
+        }
+    }
+}
+
+/// The outcome of verifying & appraising a saved evidence bundle.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Outcome {
+    passed: bool,
+    /// The error that failed verification followed by its causes.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    error: Vec<String>,
+    /// The trust anchor that validated each cert chain & the platform that
+    /// produced it.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cert_chains: Vec<VerifiedChain>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    appraisal: Option<AppraisalReport>,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.error.split_first() {
+            None => {
+                writeln!(f, "evidence: verified")?;
+                for chain in &self.cert_chains {
+                    writeln!(f, "{chain}")?;
+                }
+            }
+            Some((error, causes)) => {
+                writeln!(f, "evidence: FAILED: {error}")?;
+                for cause in causes {
+                    writeln!(f, "  caused by: {cause}")?;
+                }
+            }
+        }
+        match &self.appraisal {
+            Some(report) => write!(f, "{report}"),
+            None => {
+                write!(
+                    f,
+                    "result: {}",
+                    if self.passed { "PASS" } else { "FAIL" }
+                )
+            }
+        }
+    }
+}
+
+/// Verify the evidence bundle from `args` against the root certs, and if
+/// `appraise` is set appraise it against the CoRIMs & instance policy. The
+/// nonce in the bundle is trusted: there's no way to check its freshness
+/// offline.
+fn offline(
+    args: &OfflineArgs,
+    corims: Vec<PathBuf>,
+    instance_policy: Option<PathBuf>,
+    appraise: bool,
+) -> Result<ExitCode> {
+    let bundle = read_bundle(&args.bundle)?;
+    let verifier = Verifier::from_config(&VerifierConfig {
+        trust_anchors: args.root.clone(),
+        corims,
+        crls: args.crl.clone(),
+        instance_policy,
+        freshness_window: 0,
+        nonce_store: NonceStoreConfig::Memory,
+    })?;
+
+    let time = match args.at {
+        Some(at) => at.to_system_time(),
+        None => SystemTime::now(),
+    };
+    let result = verifier
+        .verify_evidence_at(
+            &bundle.evidence,
+            &bundle.nonce,
+            &bundle.user_data,
+            time,
+        )
+        .map_err(anyhow::Error::from)
+        .and_then(|cert_chains| {
+            let appraisal = match appraise {
+                true => Some(verifier.appraise(&bundle.evidence)?),
+                false => None,
+            };
+            Ok((cert_chains, appraisal))
+        });
+    let outcome = match result {
+        Ok((cert_chains, appraisal)) => Outcome {
+            passed: appraisal.as_ref().is_none_or(|r| r.is_ok()),
+            error: Vec::new(),
+            cert_chains,
+            appraisal,
+        },
+        Err(e) => Outcome {
+            passed: false,
+            error: e.chain().map(ToString::to_string).collect(),
+            cert_chains: Vec::new(),
+            appraisal: None,
+        },
+    };
+
+    if args.json {
+        print_json(&outcome)?;
+    } else {
+        println!("nonce: {}", hex::encode(&bundle.nonce));
+        println!("user data: {}", hex::encode(&bundle.user_data));
+        println!("{outcome}");
+    }
+
+    Ok(match outcome.passed {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    })
+}
+
+/// Compute the reference values for `image` & write them out.
+fn reference(
+    image: &Path,
+    algorithm: DigestAlgorithm,
+    instance_out: Option<&Path>,
+    corim_out: Option<&Path>,
+    vendor: &str,
+    tag_id: Option<&str>,
+    policy_out: Option<&Path>,
+) -> Result<()> {
+    let values = ReferenceValues::from_image(image, algorithm)?;
+
+    match instance_out {
+        Some(path) => {
+            let json = serde_json::to_vec_pretty(&values)?;
+            fs::write(path, json)
+                .with_context(|| format!("write {}", path.display()))?;
+        }
+        None => print_json(&values)?,
+    }
+
+    if let Some(path) = corim_out {
+        let file_name = image.file_name().unwrap_or_default().to_string_lossy();
+        let kdl = values.to_corim_kdl(vendor, tag_id.unwrap_or(&file_name));
+        fs::write(path, kdl)
+            .with_context(|| format!("write {}", path.display()))?;
+    }
+
+    if let Some(path) = policy_out {
+        fs::write(path, values.to_policy_kdl())
+            .with_context(|| format!("write {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Compare the evidence from two saved bundles. Like diff(1) the exit
+/// status is non-zero if they differ.
+fn diff(left: &Path, right: &Path, json: bool) -> Result<ExitCode> {
+    let left = read_bundle(left)?;
+    let right = read_bundle(right)?;
+    let diff = EvidenceDiff::new(&left.evidence, &right.evidence)?;
+
+    match json {
+        true => print_json(&diff)?,
+        false => print!("{diff}"),
+    }
+
+    Ok(match diff.is_empty() {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    })
+}
+
+/// Serve the signer protocol w/ `signer` on `listen`. Connections are
+/// handled one at a time.
+fn mock_daemon<S: AttestationSigner>(
+    signer: &S,
+    listen: &Endpoint,
+) -> Result<()> {
+    match listen {
+        Endpoint::Unix(path) => {
+            let listener = UnixListener::bind(path)
+                .with_context(|| format!("bind {listen}"))?;
+            for stream in listener.incoming() {
+                let stream = stream?;
+                protocol::set_unix_timeouts(&stream)?;
+                let reader = BufReader::new(stream.try_clone()?);
+                if let Err(e) = protocol::serve(signer, reader, stream) {
+                    eprintln!("connection failed: {e}");
+                }
+            }
+        }
+        Endpoint::Tcp(addr) => {
+            let listener = TcpListener::bind(addr)
+                .with_context(|| format!("bind {listen}"))?;
+            for stream in listener.incoming() {
+                let stream = stream?;
+                protocol::set_tcp_timeouts(&stream)?;
+                let reader = BufReader::new(stream.try_clone()?);
+                if let Err(e) = protocol::serve(signer, reader, stream) {
+                    eprintln!("connection failed: {e}");
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
+
+    match (cli.signer.endpoint, cli.signer.mock, cli.command) {
+        (_, _, Command::Verify { offline: args }) => {
+            offline(&args, Vec::new(), None, false)
+        }
+        (
+            _,
+            _,
+            Command::Appraise {
+                offline: args,
+                corim,
+                instance_policy,
+            },
+        ) => offline(&args, corim, Some(instance_policy), true),
+        (
+            _,
+            _,
+            Command::Reference {
+                image,
+                algorithm,
+                instance_out,
+                corim_out,
+                vendor,
+                tag_id,
+                policy_out,
+            },
+        ) => {
+            reference(
+                &image,
+                algorithm,
+                instance_out.as_deref(),
+                corim_out.as_deref(),
+                &vendor,
+                tag_id.as_deref(),
+                policy_out.as_deref(),
+            )?;
+            Ok(ExitCode::SUCCESS)
+        }
+        (_, _, Command::Diff { left, right, json }) => {
+            diff(&left, &right, json)
+        }
+        (_, _, Command::Inspect { inspect: args }) if args.is_offline() => {
+            let (logs, cert_chains) = args.load()?;
+            inspect(&args, &logs, &cert_chains)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        (_, Some(mock), Command::MockDaemon { listen }) => {
+            let signer = MockConfig::load(&mock)?.build()?;
+            mock_daemon(&signer, &listen)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        (Some(endpoint), _, command) => {
+            let client = Client::connect(&endpoint)
+                .with_context(|| format!("connect to {endpoint}"))?;
+            run(&client, command)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        (None, Some(mock), command) => {
+            run(&MockConfig::load(&mock)?.build()?, command)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        (None, None, _) => {
+            Err(anyhow!("one of --endpoint or --mock is required"))
+        }
+    }
+}
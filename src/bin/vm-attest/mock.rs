@@ -0,0 +1,135 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use anyhow::{Context, Result};
+use dice_verifier::{Attest, AttestMock as OxAttestMock};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use vm_attest_trait::{
+    AttestMock, AttestationSigner, RotType, VmInstanceConf,
+    instance_key::{self, InstanceKey},
+};
+
+/// The inputs for one mock hardware RoT.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RotConfig {
+    /// PEM encoded cert chain for the attestation signing key.
+    pub pki_path: PathBuf,
+    /// The hubpack encoded measurement log.
+    pub log: PathBuf,
+    /// PEM encoded attestation signing key.
+    pub signing_key: PathBuf,
+}
+
+/// Configuration for an `AttestMock`:
+///
+/// ```json
+/// {
+///     "oxide-hardware": {
+///         "pki-path": "test-alias.certlist.pem",
+///         "log": "log.bin",
+///         "signing-key": "test-alias.key.pem"
+///     },
+///     "oxide-sp": {
+///         "pki-path": "test-sp-alias.certlist.pem",
+///         "log": "sp-log.bin",
+///         "signing-key": "test-sp-alias.key.pem"
+///     },
+///     "instance-cfg": "vm-instance-cfg.json",
+///     "instance-key": true
+/// }
+/// ```
+///
+/// Relative paths are resolved relative to the directory holding the config
+/// file. When `instance-key` is set the mock signs attestations w/ an
+/// `InstanceKey` certified by the signing key of the Oxide platform RoT.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct MockConfig {
+    pub oxide_hardware: RotConfig,
+    pub oxide_sp: RotConfig,
+    pub instance_cfg: PathBuf,
+    #[serde(default)]
+    pub instance_key: bool,
+}
+
+impl MockConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let config = fs::read_to_string(path)
+            .with_context(|| format!("read {}", path.display()))?;
+        let mut config: Self = serde_json::from_str(&config)
+            .with_context(|| format!("parse {}", path.display()))?;
+
+        if let Some(dir) = path.parent() {
+            let resolve = |path: &mut PathBuf| *path = dir.join(&*path);
+            for rot in [&mut config.oxide_hardware, &mut config.oxide_sp] {
+                resolve(&mut rot.pki_path);
+                resolve(&mut rot.log);
+                resolve(&mut rot.signing_key);
+            }
+            resolve(&mut config.instance_cfg);
+        }
+
+        Ok(config)
+    }
+
+    pub fn build(&self) -> Result<AttestMock> {
+        let load = |rot: &RotConfig| {
+            OxAttestMock::load(&rot.pki_path, &rot.log, &rot.signing_key)
+                .with_context(|| {
+                    format!("load mock RoT from {}", rot.pki_path.display())
+                })
+        };
+        let oxattest_mock = load(&self.oxide_hardware)?;
+        let sp_attest_mock = load(&self.oxide_sp)?;
+
+        let instance_cfg = fs::read_to_string(&self.instance_cfg)
+            .with_context(|| format!("read {}", self.instance_cfg.display()))?;
+        let instance_cfg: VmInstanceConf = serde_json::from_str(&instance_cfg)
+            .with_context(|| {
+                format!("parse {}", self.instance_cfg.display())
+            })?;
+
+        if !self.instance_key {
+            return Ok(AttestMock::new(
+                oxattest_mock,
+                sp_attest_mock,
+                instance_cfg,
+            ));
+        }
+
+        let signing_key = &self.oxide_hardware.signing_key;
+        let alias_key = fs::read_to_string(signing_key)
+            .with_context(|| format!("read {}", signing_key.display()))?;
+        let alias_key = instance_key::signing_key_from_pem(&alias_key)
+            .context("parse alias key")?;
+        let alias_chain = oxattest_mock
+            .get_certificates()
+            .context("get alias cert chain")?;
+
+        // the instance cert binds the log from the Oxide platform RoT
+        let uuid = instance_cfg.uuid;
+        let attest =
+            AttestMock::new(oxattest_mock, sp_attest_mock, instance_cfg);
+        let platform_log = attest
+            .get_measurement_logs()
+            .context("get measurement logs")?
+            .into_iter()
+            .find(|l| l.rot() == RotType::OxideHardware)
+            .context("no measurement log from the Oxide platform RoT")?;
+        let instance_key = InstanceKey::generate(
+            &uuid,
+            &platform_log,
+            &alias_key,
+            &alias_chain[0],
+        )
+        .context("generate InstanceKey")?;
+
+        Ok(attest.with_instance_key(instance_key))
+    }
+}
@@ -0,0 +1,276 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A test harness checking that an `AttestationSigner` implementation
+//! behaves like `AttestMock`. Implementors can check their impl w/ a single
+//! call to `run`.
+
+use crate::{
+    AttestationSigner, CertChain, DecodedLog, LogDecodeError, MeasurementLog,
+    RotType, VmInstanceConf,
+    appraise::AppraisalReport,
+    verifier::{
+        Evidence, Verifier, VerifierConfig, VerifierConfigError, VerifyError,
+    },
+};
+use std::collections::HashSet;
+
+/// What the `AttestationSigner` under test is expected to produce.
+pub struct Expectations {
+    /// Configuration for the verifier that evidence from the signer must
+    /// pass: the root of the platform PKI, reference measurements & policy.
+    pub verifier: VerifierConfig,
+    /// The RoTs expected to return an attestation, in order.
+    pub rots: Vec<RotType>,
+    /// The instance config the signer is expected to report, if known.
+    pub instance: Option<VmInstanceConf>,
+}
+
+/// The ways an `AttestationSigner` can fail to conform.
+#[derive(Debug, thiserror::Error)]
+pub enum ConformanceError<E> {
+    #[error("error from the AttestationSigner")]
+    Signer(#[source] E),
+    #[error("failed to create verifier")]
+    VerifierConfig(#[from] VerifierConfigError),
+    #[error("measurement log {0} failed to decode")]
+    LogDecode(usize, #[source] LogDecodeError),
+    #[error("more than one measurement log from {0:?}")]
+    DuplicateLog(RotType),
+    #[error("the measurement log from the instance must be last")]
+    LogOrder,
+    #[error("instance config doesn't match the expected config")]
+    InstanceConf,
+    #[error("more than one cert chain for {0:?}")]
+    DuplicateCertChain(RotType),
+    #[error("cert chain for {0:?} is empty")]
+    EmptyCertChain(RotType),
+    #[error("cert {index} in the chain for {rot:?} isn't issued by the next")]
+    CertChainOrder { rot: RotType, index: usize },
+    #[error("no cert chain for the measurement log from {0:?}")]
+    NoCertChain(RotType),
+    #[error("attestations from {actual:?}, expected {expected:?}")]
+    Attestations {
+        expected: Vec<RotType>,
+        actual: Vec<RotType>,
+    },
+    #[error("attestation for one transcript verified against another")]
+    Transcript,
+    #[error("evidence failed verification")]
+    Verify(#[from] VerifyError),
+    #[error("evidence failed appraisal")]
+    Appraisal(AppraisalReport),
+}
+
+/// Check that the measurement logs decode, that there's one per RoT, and
+/// that the log from the instance is last. This is the order that the logs
+/// are concatenated in.
+fn check_logs<E>(
+    logs: &[MeasurementLog],
+    expectations: &Expectations,
+) -> Result<(), ConformanceError<E>> {
+    let mut rots = HashSet::new();
+    for (index, log) in logs.iter().enumerate() {
+        if !rots.insert(log.rot) {
+            return Err(ConformanceError::DuplicateLog(log.rot));
+        }
+        let decoded = log
+            .decode()
+            .map_err(|e| ConformanceError::LogDecode(index, e))?;
+
+        if let DecodedLog::OxideInstance(cfg) = decoded {
+            if index != logs.len() - 1 {
+                return Err(ConformanceError::LogOrder);
+            }
+            if let Some(expected) = &expectations.instance
+                && *expected != cfg
+            {
+                return Err(ConformanceError::InstanceConf);
+            }
+        }
+    }
+
+    if !rots.contains(&RotType::OxideInstance) {
+        return Err(
+            VerifyError::NoMeasurementLog(RotType::OxideInstance).into()
+        );
+    }
+
+    Ok(())
+}
+
+/// Check that there's one cert chain per RoT, & that each chain is ordered
+/// leaf first w/ each cert issued by the next.
+fn check_cert_chains<E>(
+    cert_chains: &[CertChain],
+    logs: &[MeasurementLog],
+) -> Result<(), ConformanceError<E>> {
+    let mut rots = HashSet::new();
+    for cert_chain in cert_chains {
+        let rot = cert_chain.rot;
+        if !rots.insert(rot) {
+            return Err(ConformanceError::DuplicateCertChain(rot));
+        }
+        if cert_chain.pki_path.is_empty() {
+            return Err(ConformanceError::EmptyCertChain(rot));
+        }
+        for (index, pair) in cert_chain.pki_path.windows(2).enumerate() {
+            if pair[0].tbs_certificate.issuer != pair[1].tbs_certificate.subject
+            {
+                return Err(ConformanceError::CertChainOrder { rot, index });
+            }
+        }
+    }
+
+    // every hardware RoT that produced a log must have a cert chain
+    for log in logs {
+        if log.rot != RotType::OxideInstance && !rots.contains(&log.rot) {
+            return Err(ConformanceError::NoCertChain(log.rot));
+        }
+    }
+
+    Ok(())
+}
+
+/// Exercise the `AttestationSigner` contract end to end:
+/// - measurement logs decode, one per RoT w/ the instance log last
+/// - cert chains are ordered leaf first, one per RoT w/ a log
+/// - `attest` returns an attestation from each of `expectations.rots` in
+///   order
+/// - the evidence passes verification & appraisal w/ a `Verifier` created
+///   from `expectations.verifier`, which requires the transcript to be
+///   constructed as `sha256(instance_log | nonce | user_data)`
+/// - attestations aren't accepted for a different nonce or user data
+pub fn run<S: AttestationSigner>(
+    signer: &S,
+    expectations: &Expectations,
+) -> Result<(), ConformanceError<S::Error>> {
+    let logs = signer
+        .get_measurement_logs()
+        .map_err(ConformanceError::Signer)?;
+    check_logs(&logs, expectations)?;
+
+    let cert_chains =
+        signer.get_cert_chains().map_err(ConformanceError::Signer)?;
+    check_cert_chains(&cert_chains, &logs)?;
+
+    let verifier = Verifier::from_config(&expectations.verifier)?;
+    let user_data = b"conformance user data";
+    let nonce = verifier.issue_nonce()?;
+    let evidence = Evidence::collect(signer, &nonce, user_data)
+        .map_err(ConformanceError::Signer)?;
+
+    let actual: Vec<RotType> =
+        evidence.attestations.iter().map(|a| a.rot).collect();
+    if actual != expectations.rots {
+        return Err(ConformanceError::Attestations {
+            expected: expectations.rots.clone(),
+            actual,
+        });
+    }
+
+    let report = verifier.verify(&evidence, &nonce, user_data)?;
+    if !report.is_ok() {
+        return Err(ConformanceError::Appraisal(report));
+    }
+
+    // the same evidence must not verify for another transcript
+    let nonce = verifier.issue_nonce()?;
+    let evidence = Evidence::collect(signer, &nonce, user_data)
+        .map_err(ConformanceError::Signer)?;
+    let other = verifier.issue_nonce()?;
+    if verifier.verify(&evidence, &other, user_data).is_ok() {
+        return Err(ConformanceError::Transcript);
+    }
+
+    let nonce = verifier.issue_nonce()?;
+    let evidence = Evidence::collect(signer, &nonce, user_data)
+        .map_err(ConformanceError::Signer)?;
+    if verifier
+        .verify(&evidence, &nonce, b"other user data")
+        .is_ok()
+    {
+        return Err(ConformanceError::Transcript);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fault::{Call, Fault, FaultSigner, Trigger},
+        test::{config, setup, setup_instance_key, verifier_config},
+    };
+    use std::fs;
+
+    fn expectations(rots: Vec<RotType>) -> Expectations {
+        let instance = fs::read_to_string(config::VM_INSTANCE_CFG)
+            .expect("read VM_INSTANCE_CFG to string");
+        let instance: VmInstanceConf =
+            serde_json::from_str(&instance).expect("parse VM_INSTANCE_CFG");
+
+        Expectations {
+            verifier: verifier_config(),
+            rots,
+            instance: Some(instance),
+        }
+    }
+
+    #[test]
+    fn attest_mock_conforms() {
+        run(
+            &setup(),
+            &expectations(vec![RotType::OxideHardware, RotType::OxideSp]),
+        )
+        .expect("AttestMock conforms");
+
+        run(
+            &setup_instance_key(),
+            &expectations(vec![RotType::OxideSp, RotType::OxideInstance]),
+        )
+        .expect("AttestMock w/ InstanceKey conforms");
+    }
+
+    #[test]
+    fn nonconforming() {
+        let expectations =
+            expectations(vec![RotType::OxideHardware, RotType::OxideSp]);
+
+        let signer = FaultSigner::new(setup()).with_fault(
+            Call::Attest,
+            Trigger::Always,
+            Fault::CorruptSignature(RotType::OxideSp),
+        );
+        let result = run(&signer, &expectations);
+        assert!(matches!(
+            result,
+            Err(ConformanceError::Verify(VerifyError::Attestation(_)))
+        ));
+
+        // cert chain ordered root first
+        let mut cert_chain = setup()
+            .get_cert_chains()
+            .expect("get_cert_chains")
+            .swap_remove(0);
+        cert_chain.pki_path.reverse();
+        let signer = FaultSigner::new(setup()).with_fault(
+            Call::GetCertChains,
+            Trigger::Always,
+            Fault::ForeignCertChain(cert_chain),
+        );
+        let result = run(&signer, &expectations);
+        assert!(matches!(
+            result,
+            Err(ConformanceError::CertChainOrder { index: 0, .. })
+        ));
+
+        let result = run(
+            &setup(),
+            &self::expectations(vec![RotType::OxideSp, RotType::OxideHardware]),
+        );
+        assert!(matches!(result, Err(ConformanceError::Attestations { .. })));
+    }
+}
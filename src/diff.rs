@@ -0,0 +1,381 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Compare the evidence from two VMs, or from one VM at two points in time.
+//! The comparison covers the entries in the measurement logs from the
+//! hardware RoTs, the raw logs from unknown RoTs, the identity of each cert
+//! in the cert chains and the fields of the `VmInstanceConf`. Attestations are expected to differ and
+//! aren't compared.
+
+use crate::{
+    Measurement, RotType,
+    inspect::{CertInspection, InspectError, Inspection, LogContents},
+    policy,
+    verifier::Evidence,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// Where a difference was found.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum DiffLocation {
+    /// The entry at `index` in the measurement log from `rot`.
+    Log { rot: RotType, index: usize },
+    /// The whole measurement log from the unknown RoT `rot`.
+    RawLog { rot: RotType },
+    /// A field of the cert at `index` in the cert chain for `rot`.
+    Cert {
+        rot: RotType,
+        index: usize,
+        field: &'static str,
+    },
+    /// A field of the `VmInstanceConf`, named as in a policy `Rule`.
+    Instance { field: String },
+}
+
+impl fmt::Display for DiffLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Log { rot, index } => write!(f, "{rot} log[{index}]"),
+            Self::RawLog { rot } => write!(f, "{rot} log"),
+            Self::Cert { rot, index, field } => {
+                write!(f, "{rot} cert chain[{index}] {field}")
+            }
+            Self::Instance { field } => write!(f, "instance {field}"),
+        }
+    }
+}
+
+/// A value that differs between the two pieces of evidence. `None` means
+/// the value is missing from that side.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Difference {
+    pub location: DiffLocation,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let missing = "<missing>";
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.location,
+            self.left.as_deref().unwrap_or(missing),
+            self.right.as_deref().unwrap_or(missing),
+        )
+    }
+}
+
+/// Every difference between two pieces of evidence.
+#[derive(Debug, Default, Serialize)]
+pub struct EvidenceDiff {
+    pub differences: Vec<Difference>,
+}
+
+impl EvidenceDiff {
+    /// Compare `left` w/ `right`.
+    pub fn new(
+        left: &Evidence,
+        right: &Evidence,
+    ) -> Result<Self, InspectError> {
+        let left = Inspection::new(&left.logs, &left.cert_chains)?;
+        let right = Inspection::new(&right.logs, &right.cert_chains)?;
+
+        let mut diff = Self::default();
+        diff.logs(&left, &right);
+        diff.raw_logs(&left, &right);
+        diff.cert_chains(&left, &right);
+        diff.instance(&left, &right);
+
+        Ok(diff)
+    }
+
+    /// Returns `true` if no differences were found.
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    fn push(
+        &mut self,
+        location: DiffLocation,
+        left: Option<String>,
+        right: Option<String>,
+    ) {
+        if left != right {
+            self.differences.push(Difference {
+                location,
+                left,
+                right,
+            });
+        }
+    }
+
+    /// Compare the log entries from each hardware RoT by position.
+    fn logs(&mut self, left: &Inspection, right: &Inspection) {
+        let (left, right) = (hardware_logs(left), hardware_logs(right));
+        for id in keys(&left, &right) {
+            let rot = RotType::from_id(id);
+            let left = left.get(&id).copied().unwrap_or_default();
+            let right = right.get(&id).copied().unwrap_or_default();
+            for index in 0..left.len().max(right.len()) {
+                self.push(
+                    DiffLocation::Log { rot, index },
+                    left.get(index).map(ToString::to_string),
+                    right.get(index).map(ToString::to_string),
+                );
+            }
+        }
+    }
+
+    /// Compare the logs from unknown RoTs as a whole.
+    fn raw_logs(&mut self, left: &Inspection, right: &Inspection) {
+        let (left, right) = (raw_logs(left), raw_logs(right));
+        for id in keys(&left, &right) {
+            self.push(
+                DiffLocation::RawLog {
+                    rot: RotType::from_id(id),
+                },
+                left.get(&id).map(ToString::to_string),
+                right.get(&id).map(ToString::to_string),
+            );
+        }
+    }
+
+    /// Compare the identity of each cert in the chain for each RoT by
+    /// position.
+    fn cert_chains(&mut self, left: &Inspection, right: &Inspection) {
+        let (left, right) = (cert_chains(left), cert_chains(right));
+        for id in keys(&left, &right) {
+            let rot = RotType::from_id(id);
+            let left = left.get(&id).copied().unwrap_or_default();
+            let right = right.get(&id).copied().unwrap_or_default();
+            for index in 0..left.len().max(right.len()) {
+                for (field, value) in CERT_FIELDS {
+                    self.push(
+                        DiffLocation::Cert { rot, index, field },
+                        left.get(index).map(value),
+                        right.get(index).map(value),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Compare the fields of the `VmInstanceConf`.
+    fn instance(&mut self, left: &Inspection, right: &Inspection) {
+        let (left, right) = (instance_fields(left), instance_fields(right));
+        for field in keys(&left, &right) {
+            self.push(
+                DiffLocation::Instance {
+                    field: field.clone(),
+                },
+                left.get(&field).cloned(),
+                right.get(&field).cloned(),
+            );
+        }
+    }
+}
+
+/// The keys from both `left` & `right`, in order.
+fn keys<K: Clone + Ord, V>(
+    left: &BTreeMap<K, V>,
+    right: &BTreeMap<K, V>,
+) -> BTreeSet<K> {
+    left.keys().chain(right.keys()).cloned().collect()
+}
+
+/// The entries from the log of each hardware RoT keyed by RoT id.
+fn hardware_logs(inspection: &Inspection) -> BTreeMap<u32, &[Measurement]> {
+    inspection
+        .logs
+        .iter()
+        .filter_map(|log| match &log.contents {
+            LogContents::Measurements { measurements } => {
+                Some((log.rot.id(), measurements.as_slice()))
+            }
+            LogContents::Instance { .. } | LogContents::Raw { .. } => None,
+        })
+        .collect()
+}
+
+/// The hex encoded log from each unknown RoT keyed by RoT id.
+fn raw_logs(inspection: &Inspection) -> BTreeMap<u32, &str> {
+    inspection
+        .logs
+        .iter()
+        .filter_map(|log| match &log.contents {
+            LogContents::Raw { data } => Some((log.rot.id(), data.as_str())),
+            LogContents::Measurements { .. } | LogContents::Instance { .. } => {
+                None
+            }
+        })
+        .collect()
+}
+
+/// The certs in the chain for each RoT keyed by RoT id.
+fn cert_chains(inspection: &Inspection) -> BTreeMap<u32, &[CertInspection]> {
+    inspection
+        .cert_chains
+        .iter()
+        .map(|chain| (chain.rot.id(), chain.certs.as_slice()))
+        .collect()
+}
+
+/// The fields of the `VmInstanceConf`, if there is one.
+fn instance_fields(inspection: &Inspection) -> BTreeMap<String, String> {
+    inspection
+        .logs
+        .iter()
+        .find_map(|log| match &log.contents {
+            LogContents::Instance { config } => Some(policy::fields(config)),
+            LogContents::Measurements { .. } | LogContents::Raw { .. } => None,
+        })
+        .unwrap_or_default()
+}
+
+/// The name of a field from a cert & a function to get its value.
+type CertField = (&'static str, fn(&CertInspection) -> String);
+
+/// The fields that identify a cert.
+const CERT_FIELDS: [CertField; 4] = [
+    ("subject", |c| c.subject.clone()),
+    ("issuer", |c| c.issuer.clone()),
+    ("serial-number", |c| c.serial_number.clone()),
+    ("fwids", |c| {
+        c.fwids
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }),
+];
+
+impl fmt::Display for EvidenceDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no differences");
+        }
+        for difference in &self.differences {
+            writeln!(f, "{difference}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        MeasurementLog, Nonce, VmInstanceConf,
+        test::{setup, setup_instance_key},
+    };
+
+    const USER_DATA: [u8; 32] = [0u8; 32];
+
+    fn evidence() -> Evidence {
+        Evidence::collect(&setup(), &Nonce::from_array([0u8; 32]), &USER_DATA)
+            .expect("evidence")
+    }
+
+    #[test]
+    fn diff_identical() {
+        // a different nonce only changes the attestations
+        let other = Evidence::collect(
+            &setup(),
+            &Nonce::from_array([1u8; 32]),
+            &USER_DATA,
+        )
+        .expect("evidence");
+
+        let diff = EvidenceDiff::new(&evidence(), &other).expect("diff");
+        assert!(diff.is_empty(), "{diff}");
+    }
+
+    #[test]
+    fn diff_instance_conf() {
+        let left = evidence();
+        let mut right = evidence();
+
+        let log = right
+            .logs
+            .iter_mut()
+            .find(|l| l.rot == RotType::OxideInstance)
+            .expect("instance log");
+        let mut cfg: VmInstanceConf =
+            serde_json::from_slice(&log.data).expect("decode instance log");
+        cfg.image_digest.digest = "00".repeat(32);
+        *log = MeasurementLog::new(
+            RotType::OxideInstance,
+            serde_json::to_vec(&cfg).expect("encode instance log"),
+        );
+
+        let diff = EvidenceDiff::new(&left, &right).expect("diff");
+        assert_eq!(diff.differences.len(), 1, "{diff}");
+        assert_eq!(
+            diff.differences[0].location,
+            DiffLocation::Instance {
+                field: "image-digest.digest".to_string()
+            }
+        );
+        assert_eq!(diff.differences[0].right, Some("00".repeat(32)));
+    }
+
+    #[test]
+    fn diff_cert_chain() {
+        // the instance key adds a cert to the chain for the instance
+        let left = evidence();
+        let right = Evidence::collect(
+            &setup_instance_key(),
+            &Nonce::from_array([0u8; 32]),
+            &USER_DATA,
+        )
+        .expect("evidence");
+
+        let diff = EvidenceDiff::new(&left, &right).expect("diff");
+        assert!(diff.differences.iter().any(|d| matches!(
+            d.location,
+            DiffLocation::Cert {
+                rot: RotType::OxideInstance,
+                index: 0,
+                field: "subject",
+            }
+        ) && d.left.is_none()));
+        // the hardware logs are unchanged
+        assert!(
+            !diff
+                .differences
+                .iter()
+                .any(|d| matches!(d.location, DiffLocation::Log { .. })),
+            "{diff}"
+        );
+    }
+
+    #[test]
+    fn diff_unknown_log() {
+        let left = evidence();
+        let mut right = evidence();
+        right
+            .logs
+            .push(MeasurementLog::new(RotType::from_id(7), vec![0xde, 0xad]));
+
+        let diff = EvidenceDiff::new(&left, &right).expect("diff");
+        assert_eq!(
+            diff.differences,
+            [Difference {
+                location: DiffLocation::RawLog {
+                    rot: RotType::from_id(7)
+                },
+                left: None,
+                right: Some("dead".to_string()),
+            }]
+        );
+    }
+}
@@ -0,0 +1,342 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{
+    Attestation, AttestationSigner, CertChain, ErrorKind, MeasurementLog,
+    Nonce, RotType, SignerError,
+};
+use std::{collections::HashMap, sync::Mutex, thread, time::Duration};
+
+/// The functions from `AttestationSigner` that a fault can be injected into.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Call {
+    Attest,
+    GetMeasurementLogs,
+    GetCertChains,
+}
+
+/// The invocations of a `Call` that a fault is injected into.
+#[derive(Clone, Copy, Debug)]
+pub enum Trigger {
+    /// Every invocation.
+    Always,
+    /// Only the nth invocation, counting from 0.
+    Nth(usize),
+}
+
+impl Trigger {
+    fn fires(&self, count: usize) -> bool {
+        match self {
+            Trigger::Always => true,
+            Trigger::Nth(n) => *n == count,
+        }
+    }
+}
+
+/// A fault injected by the `FaultSigner`. Faults that modify the data
+/// returned only apply to the `Call` that returns that data and are ignored
+/// for all others.
+#[derive(Clone)]
+pub enum Fault {
+    /// Fail the call w/ `FaultError::Injected` of the given kind.
+    Error(ErrorKind),
+    /// Flip a bit in the signature in the attestation from the given RoT.
+    /// Applies to `Call::Attest`.
+    CorruptSignature(RotType),
+    /// Replace the measurement log from the RoT that produced the provided
+    /// log. Applies to `Call::GetMeasurementLogs`.
+    SwapLog(MeasurementLog),
+    /// Replace the cert chain for the RoT that the provided chain is
+    /// associated w/. This is intended for chains from another PKI.
+    /// Applies to `Call::GetCertChains`.
+    ForeignCertChain(CertChain),
+    /// Delay the call.
+    Latency(Duration),
+}
+
+/// Errors returned by a `FaultSigner`.
+#[derive(Debug, thiserror::Error)]
+pub enum FaultError<E> {
+    #[error("injected {kind} fault in {call:?}")]
+    Injected { call: Call, kind: ErrorKind },
+    #[error("error from the wrapped AttestationSigner")]
+    Signer(#[source] E),
+}
+
+impl<E: SignerError + 'static> SignerError for FaultError<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            FaultError::Injected { kind, .. } => *kind,
+            FaultError::Signer(e) => e.kind(),
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            FaultError::Injected { .. } => None,
+            FaultError::Signer(e) => e.retry_after(),
+        }
+    }
+}
+
+/// Wraps an `AttestationSigner` injecting faults into chosen calls. This
+/// allows consumers of the API to exercise their error handling against a
+/// deterministic mock.
+pub struct FaultSigner<S> {
+    inner: S,
+    faults: Vec<(Call, Trigger, Fault)>,
+    counts: Mutex<HashMap<Call, usize>>,
+}
+
+impl<S: AttestationSigner> FaultSigner<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            faults: Vec::new(),
+            counts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Inject `fault` into the invocations of `call` selected by `trigger`.
+    pub fn with_fault(
+        mut self,
+        call: Call,
+        trigger: Trigger,
+        fault: Fault,
+    ) -> Self {
+        self.faults.push((call, trigger, fault));
+        self
+    }
+
+    /// Get the faults to inject into this invocation of `call`. Latency is
+    /// injected & the call fails here if required.
+    fn faults(&self, call: Call) -> Result<Vec<&Fault>, FaultError<S::Error>> {
+        let count = {
+            let mut counts = self.counts.lock().expect("counts lock poisoned");
+            let count = counts.entry(call).or_default();
+            *count += 1;
+            *count - 1
+        };
+
+        let faults: Vec<&Fault> = self
+            .faults
+            .iter()
+            .filter(|(c, t, _)| *c == call && t.fires(count))
+            .map(|(_, _, f)| f)
+            .collect();
+
+        for fault in &faults {
+            match fault {
+                Fault::Latency(duration) => thread::sleep(*duration),
+                Fault::Error(kind) => {
+                    return Err(FaultError::Injected { call, kind: *kind });
+                }
+                _ => (),
+            }
+        }
+
+        Ok(faults)
+    }
+}
+
+impl<S: AttestationSigner> AttestationSigner for FaultSigner<S> {
+    type Error = FaultError<S::Error>;
+
+    fn attest(
+        &self,
+        nonce: &Nonce,
+        user_data: &[u8],
+    ) -> Result<Vec<Attestation>, Self::Error> {
+        let faults = self.faults(Call::Attest)?;
+        let mut attestations = self
+            .inner
+            .attest(nonce, user_data)
+            .map_err(FaultError::Signer)?;
+
+        for fault in faults {
+            if let Fault::CorruptSignature(rot) = fault {
+                // the signature is the last field in the attestation from
+                // each RoT
+                if let Some(byte) = attestations
+                    .iter_mut()
+                    .find(|a| a.rot == *rot)
+                    .and_then(|a| a.data.last_mut())
+                {
+                    *byte ^= 0x01;
+                }
+            }
+        }
+
+        Ok(attestations)
+    }
+
+    fn get_measurement_logs(&self) -> Result<Vec<MeasurementLog>, Self::Error> {
+        let faults = self.faults(Call::GetMeasurementLogs)?;
+        let mut logs = self
+            .inner
+            .get_measurement_logs()
+            .map_err(FaultError::Signer)?;
+
+        for fault in faults {
+            if let Fault::SwapLog(swap) = fault {
+                for log in logs.iter_mut().filter(|l| l.rot == swap.rot) {
+                    *log = swap.clone();
+                }
+            }
+        }
+
+        Ok(logs)
+    }
+
+    fn get_cert_chains(&self) -> Result<Vec<CertChain>, Self::Error> {
+        let faults = self.faults(Call::GetCertChains)?;
+        let mut cert_chains =
+            self.inner.get_cert_chains().map_err(FaultError::Signer)?;
+
+        for fault in faults {
+            if let Fault::ForeignCertChain(foreign) = fault {
+                for chain in
+                    cert_chains.iter_mut().filter(|c| c.rot == foreign.rot)
+                {
+                    *chain = foreign.clone();
+                }
+            }
+        }
+
+        Ok(cert_chains)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        test::{config, setup, verifier_config},
+        verifier::{Evidence, Verifier, VerifyError},
+    };
+    use std::{fs, time::Instant};
+    use x509_cert::Certificate;
+
+    const USER_DATA: [u8; 32] = [0u8; 32];
+
+    /// Collect evidence from `signer` & verify it.
+    fn verify<S: AttestationSigner>(signer: &S) -> Result<(), VerifyError>
+    where
+        S::Error: std::fmt::Debug,
+    {
+        let verifier = Verifier::from_config(&verifier_config())
+            .expect("Verifier from config");
+        let nonce = verifier.issue_nonce().expect("issue nonce");
+        let evidence =
+            Evidence::collect(signer, &nonce, &USER_DATA).expect("evidence");
+
+        let report = verifier.verify(&evidence, &nonce, &USER_DATA)?;
+        assert!(report.is_ok(), "{report}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn no_faults() {
+        verify(&FaultSigner::new(setup())).expect("verify w/o faults");
+    }
+
+    #[test]
+    fn injected_error() {
+        let nonce = Nonce::from_array([0u8; 32]);
+        let signer = FaultSigner::new(setup()).with_fault(
+            Call::Attest,
+            Trigger::Nth(1),
+            Fault::Error(ErrorKind::Transient),
+        );
+
+        signer.attest(&nonce, &USER_DATA).expect("first attest");
+        match signer.attest(&nonce, &USER_DATA) {
+            Err(
+                e @ FaultError::Injected {
+                    call: Call::Attest, ..
+                },
+            ) => {
+                assert_eq!(e.kind(), ErrorKind::Transient);
+                assert!(e.kind().is_retryable());
+            }
+            _ => panic!("expected injected error"),
+        }
+        signer.attest(&nonce, &USER_DATA).expect("third attest");
+
+        // other calls are unaffected
+        signer.get_measurement_logs().expect("get_measurement_logs");
+        signer.get_cert_chains().expect("get_cert_chains");
+    }
+
+    #[test]
+    fn corrupt_signature() {
+        for rot in [RotType::OxideHardware, RotType::OxideSp] {
+            let signer = FaultSigner::new(setup()).with_fault(
+                Call::Attest,
+                Trigger::Always,
+                Fault::CorruptSignature(rot),
+            );
+            let result = verify(&signer);
+            assert!(matches!(result, Err(VerifyError::Attestation(_))));
+        }
+    }
+
+    #[test]
+    fn swap_log() {
+        // replace the log from the Oxide platform RoT w/ the log from the SP
+        let sp_log = setup()
+            .get_measurement_logs()
+            .expect("get_measurement_logs")
+            .into_iter()
+            .find(|l| l.rot == RotType::OxideSp)
+            .expect("SP measurement log");
+        let swap = MeasurementLog {
+            rot: RotType::OxideHardware,
+            data: sp_log.data,
+        };
+
+        let signer = FaultSigner::new(setup()).with_fault(
+            Call::GetMeasurementLogs,
+            Trigger::Always,
+            Fault::SwapLog(swap),
+        );
+        let result = verify(&signer);
+        assert!(matches!(result, Err(VerifyError::Attestation(_))));
+    }
+
+    #[test]
+    fn foreign_cert_chain() {
+        let pki_path =
+            fs::read(config::FOREIGN_PKIPATH).expect("read foreign cert chain");
+        let pki_path = Certificate::load_pem_chain(&pki_path)
+            .expect("parse foreign cert chain");
+        let foreign = CertChain {
+            rot: RotType::OxideHardware,
+            pki_path,
+        };
+
+        let signer = FaultSigner::new(setup()).with_fault(
+            Call::GetCertChains,
+            Trigger::Always,
+            Fault::ForeignCertChain(foreign),
+        );
+        let result = verify(&signer);
+        assert!(matches!(result, Err(VerifyError::CertChain(_))));
+    }
+
+    #[test]
+    fn latency() {
+        let delay = Duration::from_millis(50);
+        let signer = FaultSigner::new(setup()).with_fault(
+            Call::GetCertChains,
+            Trigger::Nth(0),
+            Fault::Latency(delay),
+        );
+
+        let start = Instant::now();
+        signer.get_cert_chains().expect("get_cert_chains");
+        assert!(start.elapsed() >= delay);
+    }
+}
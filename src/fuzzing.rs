@@ -0,0 +1,19 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Internal decoders exposed to the fuzz targets in fuzz/. Only built w/
+//! the `fuzzing` feature.
+
+use crate::{
+    Measurement,
+    appraise::{self, AppraisalError},
+};
+use x509_cert::Certificate;
+
+/// Get the FWIDs from the DICE TcbInfo extension in `cert`.
+pub fn cert_fwids(
+    cert: &Certificate,
+) -> Result<Vec<Measurement>, AppraisalError> {
+    appraise::cert_fwids(cert)
+}
@@ -0,0 +1,280 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Human readable descriptions of the measurement logs & cert chains from
+//! an `AttestationSigner`. Each description is also serializable as JSON.
+
+use crate::{
+    CertChain, DecodedLog, LogDecodeError, Measurement, MeasurementLog,
+    RotType, VmInstanceConf,
+    appraise::{self, AppraisalError},
+};
+use serde::Serialize;
+use std::fmt;
+use x509_cert::{Certificate, ext::pkix::CertificatePolicies};
+
+#[derive(Debug, thiserror::Error)]
+pub enum InspectError {
+    #[error("failed to decode measurement log")]
+    LogDecode(#[from] LogDecodeError),
+    #[error("failed to decode certificate extension")]
+    Extension(#[from] der::Error),
+    #[error("failed to get FWIDs from certificate")]
+    Fwid(#[from] AppraisalError),
+}
+
+/// The contents of a decoded `MeasurementLog`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum LogContents {
+    /// The entries from the hubpacked log of a hardware RoT, in order.
+    Measurements { measurements: Vec<Measurement> },
+    /// The `VmInstanceConf` from the instance log.
+    Instance { config: VmInstanceConf },
+    /// The hex encoded log from a RoT this crate can't decode.
+    Raw { data: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct LogInspection {
+    pub rot: RotType,
+    #[serde(flatten)]
+    pub contents: LogContents,
+}
+
+impl LogInspection {
+    /// Decode `log`. The log from an unknown RoT is kept as raw bytes.
+    pub fn new(log: &MeasurementLog) -> Result<Self, InspectError> {
+        if let RotType::Unknown(_) = log.rot {
+            return Ok(Self {
+                rot: log.rot,
+                contents: LogContents::Raw {
+                    data: hex::encode(&log.data),
+                },
+            });
+        }
+
+        let contents = match log.decode()? {
+            DecodedLog::OxideHardware(log) | DecodedLog::OxideSp(log) => {
+                LogContents::Measurements {
+                    measurements: log.iter().map(Measurement::from).collect(),
+                }
+            }
+            DecodedLog::OxideInstance(config) => {
+                LogContents::Instance { config }
+            }
+        };
+
+        Ok(Self {
+            rot: log.rot,
+            contents,
+        })
+    }
+}
+
+impl fmt::Display for LogInspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "measurement log from {}:", self.rot)?;
+        match &self.contents {
+            LogContents::Measurements { measurements } => {
+                for (index, measurement) in measurements.iter().enumerate() {
+                    writeln!(f, "  [{index}] {measurement}")?;
+                }
+            }
+            LogContents::Instance { config } => {
+                writeln!(f, "  uuid: {}", config.uuid)?;
+                writeln!(f, "  image-digest: {}", config.image_digest)?;
+            }
+            LogContents::Raw { data } => writeln!(f, "  data: {data}")?,
+        }
+
+        Ok(())
+    }
+}
+
+/// The fields of a certificate that matter for verification & appraisal.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CertInspection {
+    pub subject: String,
+    pub issuer: String,
+    pub serial_number: String,
+    pub not_before: String,
+    pub not_after: String,
+    /// OIDs from the certificate policies extension.
+    pub policies: Vec<String>,
+    /// FWIDs from the DICE TcbInfo extension.
+    pub fwids: Vec<Measurement>,
+}
+
+impl CertInspection {
+    pub fn new(cert: &Certificate) -> Result<Self, InspectError> {
+        let tbs = &cert.tbs_certificate;
+        let policies = match tbs.get::<CertificatePolicies>()? {
+            Some((_, policies)) => policies
+                .0
+                .iter()
+                .map(|p| p.policy_identifier.to_string())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            subject: tbs.subject.to_string(),
+            issuer: tbs.issuer.to_string(),
+            serial_number: tbs.serial_number.to_string(),
+            not_before: tbs.validity.not_before.to_string(),
+            not_after: tbs.validity.not_after.to_string(),
+            policies,
+            fwids: appraise::cert_fwids(cert)?,
+        })
+    }
+}
+
+impl fmt::Display for CertInspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "subject: {}", self.subject)?;
+        writeln!(f, "issuer: {}", self.issuer)?;
+        writeln!(f, "serial number: {}", self.serial_number)?;
+        writeln!(f, "validity: {} - {}", self.not_before, self.not_after)?;
+        for policy in &self.policies {
+            writeln!(f, "policy: {policy}")?;
+        }
+        for fwid in &self.fwids {
+            writeln!(f, "fwid: {fwid}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CertChainInspection {
+    pub rot: RotType,
+    /// The certs in the chain, leaf first.
+    pub certs: Vec<CertInspection>,
+}
+
+impl CertChainInspection {
+    pub fn new(cert_chain: &CertChain) -> Result<Self, InspectError> {
+        Ok(Self {
+            rot: cert_chain.rot,
+            certs: cert_chain
+                .pki_path
+                .iter()
+                .map(CertInspection::new)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl fmt::Display for CertChainInspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "cert chain for {}:", self.rot)?;
+        for (index, cert) in self.certs.iter().enumerate() {
+            writeln!(f, "  [{index}]")?;
+            for line in cert.to_string().lines() {
+                writeln!(f, "    {line}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A description of every measurement log & cert chain from a signer.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Inspection {
+    pub logs: Vec<LogInspection>,
+    pub cert_chains: Vec<CertChainInspection>,
+}
+
+impl Inspection {
+    pub fn new(
+        logs: &[MeasurementLog],
+        cert_chains: &[CertChain],
+    ) -> Result<Self, InspectError> {
+        Ok(Self {
+            logs: logs
+                .iter()
+                .map(LogInspection::new)
+                .collect::<Result<_, _>>()?,
+            cert_chains: cert_chains
+                .iter()
+                .map(CertChainInspection::new)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl fmt::Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for log in &self.logs {
+            write!(f, "{log}")?;
+        }
+        for cert_chain in &self.cert_chains {
+            write!(f, "{cert_chain}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AttestationSigner, test::setup};
+
+    #[test]
+    fn inspect() {
+        let attest = setup();
+        let logs = attest.get_measurement_logs().expect("get_measurement_logs");
+        let cert_chains = attest.get_cert_chains().expect("get_cert_chains");
+
+        let inspection =
+            Inspection::new(&logs, &cert_chains).expect("inspect evidence");
+        assert_eq!(inspection.logs.len(), logs.len());
+        assert_eq!(inspection.cert_chains.len(), cert_chains.len());
+
+        for log in &inspection.logs {
+            match (&log.rot, &log.contents) {
+                (RotType::OxideInstance, LogContents::Instance { config }) => {
+                    assert_eq!(config.image_digest.algorithm, "sha-256")
+                }
+                (_, LogContents::Measurements { measurements }) => {
+                    assert!(!measurements.is_empty())
+                }
+                (rot, _) => panic!("unexpected log contents for {rot}"),
+            }
+        }
+
+        // the leaf cert CNs are defined in test-data/config.kdl & each cert
+        // is issued by the next
+        for (cert_chain, cn) in
+            inspection.cert_chains.iter().zip(["alias", "sp-alias"])
+        {
+            assert!(cert_chain.certs[0].subject.contains(&format!("CN={cn}")));
+            for pair in cert_chain.certs.windows(2) {
+                assert_eq!(pair[0].issuer, pair[1].subject);
+            }
+        }
+
+        let text = inspection.to_string();
+        assert!(text.contains("measurement log from oxide-instance:"));
+        assert!(text.contains("cert chain for oxide-hardware:"));
+    }
+
+    #[test]
+    fn inspect_unknown_log() {
+        let log = MeasurementLog::new(RotType::from_id(7), vec![0xde, 0xad]);
+
+        let inspection = LogInspection::new(&log).expect("inspect log");
+        assert!(matches!(
+            &inspection.contents,
+            LogContents::Raw { data } if data == "dead"
+        ));
+        assert!(inspection.to_string().contains("  data: dead\n"));
+    }
+}
@@ -0,0 +1,346 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::MeasurementLog;
+use const_oid::{
+    AssociatedOid, ObjectIdentifier,
+    db::{rfc4519::COMMON_NAME, rfc8410::ID_ED_25519},
+};
+use der::{
+    Decode, DecodePem, Encode, Sequence,
+    asn1::{Any, BitString, OctetString, Utf8StringRef},
+    pem::PemLabel,
+};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use sha2::{Digest, Sha256};
+use std::{str::FromStr, time::Duration};
+use uuid::Uuid;
+use x509_cert::{
+    Certificate, TbsCertificate, Version,
+    ext::{
+        Extension,
+        pkix::{BasicConstraints, KeyUsage, KeyUsages},
+    },
+    name::Name,
+    serial_number::SerialNumber,
+    spki::{AlgorithmIdentifierOwned, SubjectPublicKeyInfoOwned},
+    time::Validity,
+};
+
+/// The lifetime of the cert issued for an instance key.
+const INSTANCE_CERT_VALIDITY: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// The OID of the extension in the instance cert that binds the measurement
+/// log from the Oxide platform RoT to the instance key. The extension value
+/// is an OCTET STRING holding the sha256 digest of the hubpacked log.
+pub const PLATFORM_LOG_DIGEST: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.3.6.1.4.1.57551.2.1");
+
+/// Errors produced while creating an `InstanceKey` or verifying its cert.
+#[derive(Debug, thiserror::Error)]
+pub enum InstanceKeyError {
+    #[error("failed to generate instance key: {0}")]
+    Rng(getrandom::Error),
+    #[error("failed to encode / decode DER")]
+    Der(#[from] der::Error),
+    #[error("unsupported key algorithm: {0}")]
+    KeyAlgorithm(ObjectIdentifier),
+    #[error("malformed ed25519 key")]
+    Key,
+    #[error("invalid ed25519 signature")]
+    Signature(#[from] ed25519_dalek::SignatureError),
+    #[error("instance cert issuer doesn't match the subject of the alias cert")]
+    Issuer,
+    #[error("instance cert subject is not an instance UUID")]
+    Subject,
+    #[error("instance cert is for {cert}, measurement log is for {log}")]
+    Uuid { cert: Uuid, log: Uuid },
+    #[error("instance cert doesn't bind a platform measurement log")]
+    NoPlatformLog,
+    #[error("platform measurement log doesn't match the instance cert")]
+    PlatformLog,
+}
+
+/// The parts of a PKCS#8 `OneAsymmetricKey` needed to get an ed25519 key.
+#[derive(Sequence)]
+struct PrivateKeyInfo {
+    version: u8,
+    algorithm: AlgorithmIdentifierOwned,
+    private_key: OctetString,
+    #[asn1(context_specific = "0", tag_mode = "IMPLICIT", optional = "true")]
+    attributes: Option<Any>,
+    #[asn1(context_specific = "1", tag_mode = "IMPLICIT", optional = "true")]
+    public_key: Option<BitString>,
+}
+
+impl PemLabel for PrivateKeyInfo {
+    const PEM_LABEL: &'static str = "PRIVATE KEY";
+}
+
+/// Parse a PEM encoded PKCS#8 ed25519 private key.
+pub fn signing_key_from_pem(pem: &str) -> Result<SigningKey, InstanceKeyError> {
+    let info = PrivateKeyInfo::from_pem(pem)?;
+    if info.algorithm.oid != ID_ED_25519 {
+        return Err(InstanceKeyError::KeyAlgorithm(info.algorithm.oid));
+    }
+
+    // the private key is itself a DER encoded OCTET STRING
+    let key = OctetString::from_der(info.private_key.as_bytes())?;
+    let key: [u8; 32] = key
+        .as_bytes()
+        .try_into()
+        .map_err(|_| InstanceKeyError::Key)?;
+
+    Ok(SigningKey::from_bytes(&key))
+}
+
+fn ed25519_algorithm() -> AlgorithmIdentifierOwned {
+    AlgorithmIdentifierOwned {
+        oid: ID_ED_25519,
+        parameters: None,
+    }
+}
+
+/// A signing key held by `propolis` for a single VM instance. The key is
+/// certified by the platform RoT alias key when the instance is created so
+/// that attestations can be signed by `propolis` directly w/o a round trip
+/// to the hardware RoT for each one. The platform RoT doesn't attest while
+/// the instance key is in use, so the cert also carries the digest of the
+/// measurement log from the platform RoT: the log is vouched for by the
+/// alias key, not by `propolis`.
+pub struct InstanceKey {
+    signing_key: SigningKey,
+    cert: Certificate,
+}
+
+impl InstanceKey {
+    /// Generate a key for the instance identified by `uuid` and issue a cert
+    /// for it signed by the platform alias key that binds `platform_log`, the
+    /// measurement log from the platform RoT. `alias_key` & `alias_cert`
+    /// stand in for the operation that the platform RoT would provide to
+    /// certify a key.
+    pub fn generate(
+        uuid: &Uuid,
+        platform_log: &MeasurementLog,
+        alias_key: &SigningKey,
+        alias_cert: &Certificate,
+    ) -> Result<Self, InstanceKeyError> {
+        let mut key = [0u8; 32];
+        getrandom::fill(&mut key).map_err(InstanceKeyError::Rng)?;
+        let signing_key = SigningKey::from_bytes(&key);
+
+        let mut serial = [0u8; 16];
+        getrandom::fill(&mut serial).map_err(InstanceKeyError::Rng)?;
+        // serial numbers must be positive
+        serial[0] &= 0x7f;
+
+        let extensions = vec![
+            Extension {
+                extn_id: BasicConstraints::OID,
+                critical: true,
+                extn_value: OctetString::new(
+                    BasicConstraints {
+                        ca: false,
+                        path_len_constraint: None,
+                    }
+                    .to_der()?,
+                )?,
+            },
+            Extension {
+                extn_id: KeyUsage::OID,
+                critical: true,
+                extn_value: OctetString::new(
+                    KeyUsage(KeyUsages::DigitalSignature.into()).to_der()?,
+                )?,
+            },
+            Extension {
+                extn_id: PLATFORM_LOG_DIGEST,
+                critical: false,
+                extn_value: OctetString::new(
+                    OctetString::new(log_digest(platform_log))?.to_der()?,
+                )?,
+            },
+        ];
+
+        let tbs_certificate = TbsCertificate {
+            version: Version::V3,
+            serial_number: SerialNumber::new(&serial)?,
+            signature: ed25519_algorithm(),
+            issuer: alias_cert.tbs_certificate.subject.clone(),
+            validity: Validity::from_now(INSTANCE_CERT_VALIDITY)?,
+            subject: Name::from_str(&format!("CN={uuid}"))?,
+            subject_public_key_info: SubjectPublicKeyInfoOwned {
+                algorithm: ed25519_algorithm(),
+                subject_public_key: BitString::from_bytes(
+                    signing_key.verifying_key().as_bytes(),
+                )?,
+            },
+            issuer_unique_id: None,
+            subject_unique_id: None,
+            extensions: Some(extensions),
+        };
+
+        let signature = alias_key.sign(&tbs_certificate.to_der()?);
+        let cert = Certificate {
+            tbs_certificate,
+            signature_algorithm: ed25519_algorithm(),
+            signature: BitString::from_bytes(&signature.to_bytes())?,
+        };
+
+        Ok(Self { signing_key, cert })
+    }
+
+    /// The cert for this key, issued by the platform alias key.
+    pub fn cert(&self) -> &Certificate {
+        &self.cert
+    }
+
+    /// Sign the 32 bytes derived from the instance log, nonce & user data.
+    pub fn sign(&self, data: &[u8; 32]) -> Signature {
+        self.signing_key.sign(data)
+    }
+}
+
+/// Get the ed25519 public key from a cert.
+pub(crate) fn cert_key(
+    cert: &Certificate,
+) -> Result<VerifyingKey, InstanceKeyError> {
+    let spki = &cert.tbs_certificate.subject_public_key_info;
+    if spki.algorithm.oid != ID_ED_25519 {
+        return Err(InstanceKeyError::KeyAlgorithm(spki.algorithm.oid));
+    }
+    let key: [u8; 32] = spki
+        .subject_public_key
+        .as_bytes()
+        .and_then(|k| k.try_into().ok())
+        .ok_or(InstanceKeyError::Key)?;
+
+    Ok(VerifyingKey::from_bytes(&key)?)
+}
+
+/// Verify that `cert` was issued by `issuer`.
+pub(crate) fn verify_cert_signature(
+    cert: &Certificate,
+    issuer: &Certificate,
+) -> Result<(), InstanceKeyError> {
+    if cert.tbs_certificate.issuer != issuer.tbs_certificate.subject {
+        return Err(InstanceKeyError::Issuer);
+    }
+    if cert.signature_algorithm.oid != ID_ED_25519 {
+        return Err(InstanceKeyError::KeyAlgorithm(
+            cert.signature_algorithm.oid,
+        ));
+    }
+    let signature = cert
+        .signature
+        .as_bytes()
+        .ok_or(InstanceKeyError::Key)
+        .and_then(|s| Ok(Signature::from_slice(s)?))?;
+
+    Ok(cert_key(issuer)?
+        .verify_strict(&cert.tbs_certificate.to_der()?, &signature)?)
+}
+
+/// Verify a signature made by `InstanceKey::sign`.
+pub(crate) fn verify_signature(
+    cert: &Certificate,
+    data: &[u8; 32],
+    signature: &[u8],
+) -> Result<(), InstanceKeyError> {
+    let signature = Signature::from_slice(signature)?;
+
+    Ok(cert_key(cert)?.verify_strict(data, &signature)?)
+}
+
+fn log_digest(log: &MeasurementLog) -> [u8; 32] {
+    Sha256::digest(&log.data).into()
+}
+
+/// Verify that `log` is the platform measurement log bound into `cert` when
+/// the platform alias key issued it.
+pub(crate) fn verify_platform_log(
+    cert: &Certificate,
+    log: &MeasurementLog,
+) -> Result<(), InstanceKeyError> {
+    let extension = cert
+        .tbs_certificate
+        .extensions
+        .iter()
+        .flatten()
+        .find(|e| e.extn_id == PLATFORM_LOG_DIGEST)
+        .ok_or(InstanceKeyError::NoPlatformLog)?;
+    let digest = OctetString::from_der(extension.extn_value.as_bytes())?;
+
+    if digest.as_bytes() == log_digest(log) {
+        Ok(())
+    } else {
+        Err(InstanceKeyError::PlatformLog)
+    }
+}
+
+/// Get the instance UUID from the common name in the instance cert subject.
+pub(crate) fn cert_uuid(cert: &Certificate) -> Result<Uuid, InstanceKeyError> {
+    cert.tbs_certificate
+        .subject
+        .0
+        .iter()
+        .flat_map(|rdn| rdn.0.iter())
+        .find(|atav| atav.oid == COMMON_NAME)
+        .and_then(|atav| Utf8StringRef::try_from(&atav.value).ok())
+        .and_then(|cn| Uuid::parse_str(cn.as_str()).ok())
+        .ok_or(InstanceKeyError::Subject)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{RotType, test::config};
+    use std::fs;
+
+    #[test]
+    fn instance_key_cert() {
+        let alias_key = fs::read_to_string(config::ATTESTATION_SIGNER)
+            .expect("read alias key");
+        let alias_key = signing_key_from_pem(&alias_key).expect("alias key");
+        let alias_chain =
+            fs::read(config::SIGNER_PKIPATH).expect("read alias cert chain");
+        let alias_chain = Certificate::load_pem_chain(&alias_chain)
+            .expect("parse alias cert chain");
+
+        let uuid = Uuid::from_u128(0x1234_5678);
+        let platform_log = MeasurementLog {
+            rot: RotType::OxideHardware,
+            data: vec![0x5a; 64],
+        };
+        let instance_key = InstanceKey::generate(
+            &uuid,
+            &platform_log,
+            &alias_key,
+            &alias_chain[0],
+        )
+        .expect("generate instance key");
+
+        verify_cert_signature(instance_key.cert(), &alias_chain[0])
+            .expect("instance cert issued by alias key");
+        assert_eq!(cert_uuid(instance_key.cert()).expect("cert uuid"), uuid);
+        verify_platform_log(instance_key.cert(), &platform_log)
+            .expect("platform log bound into instance cert");
+        let other_log = MeasurementLog {
+            data: vec![0xa5; 64],
+            ..platform_log
+        };
+        let result = verify_platform_log(instance_key.cert(), &other_log);
+        assert!(matches!(result, Err(InstanceKeyError::PlatformLog)));
+
+        let data = [0u8; 32];
+        let signature = instance_key.sign(&data);
+        verify_signature(instance_key.cert(), &data, &signature.to_bytes())
+            .expect("verify instance signature");
+
+        // the instance cert isn't an issuer
+        let result =
+            verify_cert_signature(&alias_chain[0], instance_key.cert());
+        assert!(matches!(result, Err(InstanceKeyError::Issuer)));
+    }
+}
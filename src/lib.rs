@@ -8,13 +8,40 @@ use dice_verifier::{
     AttestMock as OxAttestMock, Attestation as OxAttestation, Log,
 };
 use hubpack::SerializedSize;
-use serde::{Deserialize, Serialize};
+use instance_key::InstanceKey;
+use merkle::{InclusionProof, MerkleTree};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
 use sha2::{Digest, Sha256};
+use std::{fmt, str::FromStr, time::Duration};
 use uuid::Uuid;
 use x509_cert::PkiPath;
 
+pub mod appraise;
+pub mod conformance;
+pub mod diff;
+pub mod fault;
+pub mod inspect;
+pub mod instance_key;
+pub mod merkle;
+pub mod nonce_store;
+pub mod pki_policy;
+pub mod platform_identity;
+pub mod policy;
+pub mod protocol;
+pub mod rate_limit;
+pub mod reference;
+pub mod revocation;
+pub mod rot;
+pub mod trust_store;
+pub mod verifier;
+
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+#[cfg(test)]
+mod tamper;
+
 /// User chosen value. Probably random data. Must not be reused.
-#[derive(Debug)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Nonce([u8; 32]);
 
 impl Nonce {
@@ -37,24 +64,295 @@ impl AsRef<[u8]> for Nonce {
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// Nonces are serialized as a hex string.
+impl Serialize for Nonce {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Nonce {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let nonce = String::deserialize(deserializer)?;
+        let mut bytes = [0u8; 32];
+        hex::decode_to_slice(nonce, &mut bytes).map_err(de::Error::custom)?;
+
+        Ok(Self(bytes))
+    }
+}
+
+/// The type of RoT that produced an `Attestation`, `MeasurementLog` or
+/// `CertChain`.
+///
+/// Each `RotType` has a stable numeric wire identifier (see `RotType::id`)
+/// and a stable string identifier (see `RotType::name`). Identifiers that
+/// aren't known to this version of the crate are represented as
+/// `RotType::Unknown` so that evidence from a newer provider can still be
+/// parsed, and then rejected as coming from an unsupported RoT.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum RotType {
     OxideHardware,
     OxideInstance,
+    /// The RoT attached to the service processor (SP) on the platform.
+    OxideSp,
+    /// A RoT not known to this version of the crate, identified by its
+    /// numeric wire identifier.
+    Unknown(UnknownRot),
+}
+
+/// The numeric wire identifier of a RoT not known to this version of the
+/// crate. This can only be constructed by `RotType::from_id` so it never
+/// holds the id of a known RoT.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct UnknownRot(u32);
+
+impl UnknownRot {
+    pub const fn id(&self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for UnknownRot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl RotType {
+    /// Get the `RotType` for the numeric wire identifier.
+    pub const fn from_id(id: u32) -> Self {
+        match id {
+            1 => Self::OxideHardware,
+            2 => Self::OxideInstance,
+            3 => Self::OxideSp,
+            id => Self::Unknown(UnknownRot(id)),
+        }
+    }
+
+    /// The numeric wire identifier for this `RotType`. These values must
+    /// never be reused or reassigned.
+    pub const fn id(&self) -> u32 {
+        match self {
+            Self::OxideHardware => 1,
+            Self::OxideInstance => 2,
+            Self::OxideSp => 3,
+            Self::Unknown(unknown) => unknown.id(),
+        }
+    }
+
+    /// The string identifier for this `RotType`, or `None` if the RoT is
+    /// unknown.
+    pub const fn name(&self) -> Option<&'static str> {
+        match self {
+            Self::OxideHardware => Some("oxide-hardware"),
+            Self::OxideInstance => Some("oxide-instance"),
+            Self::OxideSp => Some("oxide-sp"),
+            Self::Unknown(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for RotType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{name}"),
+            None => write!(f, "unknown RoT ({})", self.id()),
+        }
+    }
+}
+
+/// Error returned when parsing a `RotType` from a string that is neither a
+/// string identifier nor a numeric wire identifier.
+#[derive(Debug, thiserror::Error)]
+#[error("invalid RoT identifier: \"{0}\"")]
+pub struct RotTypeParseError(String);
+
+impl FromStr for RotType {
+    type Err = RotTypeParseError;
+
+    /// Parse a `RotType` from its string identifier or the decimal
+    /// representation of its numeric wire identifier.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "oxide-hardware" => Ok(Self::OxideHardware),
+            "oxide-instance" => Ok(Self::OxideInstance),
+            "oxide-sp" => Ok(Self::OxideSp),
+            s => s
+                .parse()
+                .map(Self::from_id)
+                .map_err(|_| RotTypeParseError(s.to_string())),
+        }
+    }
+}
+
+/// `RotType`s are serialized as their numeric wire identifier. This is the
+/// only representation that an older deserializer can map to
+/// `RotType::Unknown`.
+impl Serialize for RotType {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.id())
+    }
+}
+
+/// Deserialize a `RotType` from its numeric wire identifier.
+impl<'de> Deserialize<'de> for RotType {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        u32::deserialize(deserializer).map(Self::from_id)
+    }
+}
+
+/// Serialize binary data as a hex string.
+pub(crate) mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer, de};
+
+    pub fn serialize<S: Serializer>(
+        data: &[u8],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        let data = String::deserialize(deserializer)?;
+        hex::decode(data).map_err(de::Error::custom)
+    }
+}
+
+/// Serialize a `PkiPath` as a list of PEM encoded certs, leaf first.
+mod pem_chain {
+    use serde::{Deserialize, Deserializer, Serializer, de, ser};
+    use x509_cert::{
+        Certificate, PkiPath,
+        der::{DecodePem, EncodePem, pem::LineEnding},
+    };
+
+    pub fn serialize<S: Serializer>(
+        pki_path: &PkiPath,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let pems = pki_path
+            .iter()
+            .map(|cert| cert.to_pem(LineEnding::LF))
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(ser::Error::custom)?;
+        serializer.collect_seq(pems)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<PkiPath, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|pem| Certificate::from_pem(pem).map_err(de::Error::custom))
+            .collect()
+    }
 }
 
 #[allow(dead_code)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Attestation {
     rot: RotType,
+    #[serde(with = "hex_bytes")]
     data: Vec<u8>,
+    /// Set if the RoT signed the root of a `MerkleTree` over a batch of
+    /// transcripts instead of the transcript itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    proof: Option<InclusionProof>,
+}
+
+impl Attestation {
+    pub fn rot(&self) -> RotType {
+        self.rot
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
 }
 
 #[allow(dead_code)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct MeasurementLog {
     rot: RotType,
+    #[serde(with = "hex_bytes")]
     data: Vec<u8>,
 }
 
+/// A `MeasurementLog` decoded according to its `RotType`.
+#[derive(Debug)]
+pub enum DecodedLog {
+    /// The hubpacked measurement log from the Oxide platform RoT.
+    OxideHardware(Log),
+    /// The JSON encoded `VmInstanceConf` from propolis.
+    OxideInstance(VmInstanceConf),
+    /// The hubpacked measurement log from the SP RoT.
+    OxideSp(Log),
+}
+
+/// Errors returned when the data in a `MeasurementLog` isn't encoded as
+/// expected for its `RotType`.
+#[derive(Debug, thiserror::Error)]
+pub enum LogDecodeError {
+    #[error("measurement log for {0:?} is not a hubpacked attest_data::Log")]
+    NotHubpack(RotType),
+    #[error("measurement log for {0:?} is not a JSON VmInstanceConf")]
+    NotJson(RotType, #[source] serde_json::Error),
+    #[error("measurement log from unsupported RoT: {0}")]
+    UnsupportedRot(RotType),
+}
+
+impl MeasurementLog {
+    pub fn new(rot: RotType, data: Vec<u8>) -> Self {
+        Self { rot, data }
+    }
+
+    pub fn rot(&self) -> RotType {
+        self.rot
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Decode the log data using the encoding for the associated `RotType`.
+    pub fn decode(&self) -> Result<DecodedLog, LogDecodeError> {
+        match self.rot {
+            RotType::OxideHardware | RotType::OxideSp => {
+                let (log, rest): (Log, _) = hubpack::deserialize(&self.data)
+                    .map_err(|_| LogDecodeError::NotHubpack(self.rot))?;
+                if !rest.is_empty() {
+                    return Err(LogDecodeError::NotHubpack(self.rot));
+                }
+
+                if self.rot == RotType::OxideSp {
+                    Ok(DecodedLog::OxideSp(log))
+                } else {
+                    Ok(DecodedLog::OxideHardware(log))
+                }
+            }
+            RotType::OxideInstance => serde_json::from_slice(&self.data)
+                .map(DecodedLog::OxideInstance)
+                .map_err(|e| LogDecodeError::NotJson(self.rot, e)),
+            RotType::Unknown(_) => {
+                Err(LogDecodeError::UnsupportedRot(self.rot))
+            }
+        }
+    }
+}
+
 /// A representation of the measurement log produced by the VM instance RoT.
 /// This is the log of measurements that propolis mixes into the data provided
 /// to the attestation produced by the `RotType::OxideHardware`.
@@ -65,18 +363,96 @@ pub struct VmInstanceConf {
     pub image_digest: Measurement,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(
+    Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize, Deserialize,
+)]
 pub struct Measurement {
     pub algorithm: String,
     pub digest: String,
 }
 
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.digest)
+    }
+}
+
 #[allow(dead_code)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct CertChain {
     rot: RotType,
+    #[serde(rename = "pki-path", with = "pem_chain")]
     pki_path: PkiPath,
 }
 
+impl CertChain {
+    pub fn new(rot: RotType, pki_path: PkiPath) -> Self {
+        Self { rot, pki_path }
+    }
+
+    pub fn rot(&self) -> RotType {
+        self.rot
+    }
+
+    /// The certs in the chain, leaf first.
+    pub fn pki_path(&self) -> &PkiPath {
+        &self.pki_path
+    }
+}
+
+/// The kinds of failure common to all `AttestationSigner` implementations.
+/// Each kind carries guidance for whether & when a client should retry.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// A transient failure, e.g. a glitch communicating w/ the RoT. The
+    /// request may succeed if retried now.
+    Transient,
+    /// The signer can't service requests right now, e.g. the RoT is busy or
+    /// the caller has been rate limited. The request may succeed if retried
+    /// after a delay.
+    Unavailable,
+    /// The request was malformed. It will fail again if retried unchanged.
+    InvalidInput,
+    /// The signer is broken or misconfigured. Retrying won't help.
+    Internal,
+    /// The signer doesn't support the request. Retrying won't help.
+    Unsupported,
+}
+
+impl ErrorKind {
+    /// Returns `true` if a request that failed w/ this kind of error may
+    /// succeed if retried unchanged.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ErrorKind::Transient | ErrorKind::Unavailable)
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            ErrorKind::Transient => "transient",
+            ErrorKind::Unavailable => "unavailable",
+            ErrorKind::InvalidInput => "invalid input",
+            ErrorKind::Internal => "internal",
+            ErrorKind::Unsupported => "unsupported",
+        };
+        write!(f, "{kind}")
+    }
+}
+
+/// Errors returned by an `AttestationSigner` map into an `ErrorKind` so
+/// that generic callers can decide how to handle them.
+pub trait SignerError: std::error::Error {
+    fn kind(&self) -> ErrorKind;
+
+    /// How long the client should wait before retrying, if known. This is
+    /// only meaningful when `kind().is_retryable()`.
+    fn retry_after(&self) -> Option<Duration> {
+        None
+    }
+}
+
 /// An interface for obtaining an attestation from the Oxide RoT
 ///
 /// An attestation from the Oxide RoT is an ed25519::Signature.
@@ -85,14 +461,19 @@ pub struct CertChain {
 /// instead return a serialized signature and specify the algorithms used per
 /// version.
 pub trait AttestationSigner {
-    type Error;
-
-    /// Get an attestation from the Oxide RoT entangled with the provided nonce & data.
+    type Error: SignerError + 'static;
+
+    /// Get attestations entangled with the provided nonce & data, at most
+    /// one per RoT. The attestations are in the relative order of the cert
+    /// chains from `get_cert_chains`, skipping RoTs that don't attest. Each
+    /// hardware RoT attests unless its cert chain is endorsed by a RoT that
+    /// does: e.g. w/ an `InstanceKey` the `OxideInstance` attestation
+    /// replaces the one from `OxideHardware`.
     fn attest(
         &self,
         nonce: &Nonce,
         user_data: &[u8],
-    ) -> Result<Attestation, Self::Error>;
+    ) -> Result<Vec<Attestation>, Self::Error>;
 
     /// Return all relevant measurement logs, in order of concatenation.
     fn get_measurement_logs(&self) -> Result<Vec<MeasurementLog>, Self::Error>;
@@ -104,8 +485,11 @@ pub trait AttestationSigner {
 /// Errors returned when trying to sign an attestation
 #[derive(Debug, thiserror::Error)]
 pub enum AttestMockError {
-    #[error("error deserializing data")]
-    Serialize,
+    #[error("failed to hubpack {what}: {err:?}")]
+    Serialize {
+        what: &'static str,
+        err: hubpack::Error,
+    },
     #[error("error from Oxide attestation interface")]
     OxideAttestError(#[from] OxAttestError),
     #[error("error from Oxide attestation data")]
@@ -116,65 +500,203 @@ pub enum AttestMockError {
     VmInstanceMocData(#[from] serde_json::Error),
 }
 
-/// This type mocks the `propolis` process that backs a VM.
+impl SignerError for AttestMockError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            // the mock RoTs are standing in for a device on the platform
+            AttestMockError::OxideAttestError(_) => ErrorKind::Unavailable,
+            AttestMockError::Serialize { .. }
+            | AttestMockError::OxideAttestDataError(_)
+            | AttestMockError::NoCertChain
+            | AttestMockError::VmInstanceMocData(_) => ErrorKind::Internal,
+        }
+    }
+}
+
+/// This type mocks the `propolis` process that backs a VM. The hardware RoTs
+/// on the platform are the Oxide platform RoT and the SP RoT.
 pub struct AttestMock {
     oxattest_mock: OxAttestMock,
+    sp_attest_mock: OxAttestMock,
+    instance_key: Option<InstanceKey>,
     log: VmInstanceConf,
 }
 
 impl AttestMock {
-    pub fn new(oxattest_mock: OxAttestMock, log: VmInstanceConf) -> Self {
-        Self { oxattest_mock, log }
+    pub fn new(
+        oxattest_mock: OxAttestMock,
+        sp_attest_mock: OxAttestMock,
+        log: VmInstanceConf,
+    ) -> Self {
+        Self {
+            oxattest_mock,
+            sp_attest_mock,
+            instance_key: None,
+            log,
+        }
     }
-}
 
-impl AttestationSigner for AttestMock {
-    type Error = AttestMockError;
+    /// Sign attestations w/ the provided `InstanceKey` instead of getting an
+    /// attestation from the Oxide platform RoT. The key must be certified by
+    /// the alias key of the Oxide platform RoT.
+    pub fn with_instance_key(mut self, instance_key: InstanceKey) -> Self {
+        self.instance_key = Some(instance_key);
+        self
+    }
 
-    /// `propolis` receives the nonce & user data from the caller.
-    /// It then combines this data w/ attributes describing the VM (rootfs,
-    /// instance UUID etc) and attestations from other RoTs on the platform.
-    /// The format of each attestation is dependent on the associated `RotType`.
-    /// NOTE: the order of the attestations returned is significant
-    fn attest(
+    /// The hardware RoTs on the platform. The order of this array determines
+    /// the order of the attestations, logs & cert chains.
+    fn hardware_rots(&self) -> [(RotType, &OxAttestMock); 2] {
+        [
+            (RotType::OxideHardware, &self.oxattest_mock),
+            (RotType::OxideSp, &self.sp_attest_mock),
+        ]
+    }
+
+    /// The hubpacked measurement log from the hardware RoT `mock`.
+    fn measurement_log(
+        &self,
+        rot: RotType,
+        mock: &OxAttestMock,
+    ) -> Result<MeasurementLog, AttestMockError> {
+        let log = mock.get_measurement_log()?;
+
+        let mut data = vec![0u8; Log::MAX_SIZE];
+        let len = hubpack::serialize(&mut data, &log).map_err(|err| {
+            AttestMockError::Serialize {
+                what: "measurement log",
+                err,
+            }
+        })?;
+        data.truncate(len);
+
+        Ok(MeasurementLog { rot, data })
+    }
+
+    /// The 32 bytes passed to each RoT for signing:
+    /// `sha256(instance_log | nonce | user_data)`
+    fn transcript(
         &self,
         nonce: &Nonce,
         user_data: &[u8],
-    ) -> Result<Attestation, Self::Error> {
+    ) -> Result<[u8; 32], AttestMockError> {
         let instance_cfg = serde_json::to_string(&self.log)?;
 
         let mut msg = Sha256::new();
         msg.update(instance_cfg.as_bytes());
         msg.update(nonce);
         msg.update(user_data);
-        let msg = msg.finalize();
 
-        let nonce = attest_data::Nonce { 0: msg.into() };
-        let attest = self.oxattest_mock.attest(&nonce)?;
+        Ok(msg.finalize().into())
+    }
 
-        let mut data = vec![0u8; OxAttestation::MAX_SIZE];
-        let len = hubpack::serialize(&mut data, &attest)
-            .map_err(|_| AttestMockError::Serialize)?;
-        data.truncate(len);
+    /// Get an attestation over `msg` from each hardware RoT, or from the
+    /// `InstanceKey` in place of the Oxide platform RoT.
+    fn sign(
+        &self,
+        msg: &[u8; 32],
+    ) -> Result<Vec<Attestation>, AttestMockError> {
+        let nonce = attest_data::Nonce { 0: *msg };
+
+        let mut attestations = Vec::new();
+        for (rot, mock) in self.hardware_rots() {
+            // the Oxide platform RoT endorsed the instance key when it issued
+            // the instance cert, the instance key attests in its place
+            if rot == RotType::OxideHardware && self.instance_key.is_some() {
+                continue;
+            }
 
-        Ok(Attestation {
-            rot: RotType::OxideHardware,
-            data,
-        })
+            let attest = mock.attest(&nonce)?;
+
+            let mut data = vec![0u8; OxAttestation::MAX_SIZE];
+            let len =
+                hubpack::serialize(&mut data, &attest).map_err(|err| {
+                    AttestMockError::Serialize {
+                        what: "attestation",
+                        err,
+                    }
+                })?;
+            data.truncate(len);
+
+            attestations.push(Attestation {
+                rot,
+                data,
+                proof: None,
+            });
+        }
+
+        if let Some(instance_key) = &self.instance_key {
+            let signature = instance_key.sign(msg);
+            attestations.push(Attestation {
+                rot: RotType::OxideInstance,
+                data: signature.to_bytes().to_vec(),
+                proof: None,
+            });
+        }
+
+        Ok(attestations)
     }
 
-    /// Get all measurement logs from the various RoTs on the platform.
-    fn get_measurement_logs(&self) -> Result<Vec<MeasurementLog>, Self::Error> {
-        let oxide_log = self.oxattest_mock.get_measurement_log()?;
+    /// Attest to a batch of pending challenges w/ a single signature from
+    /// each RoT. The transcript for each challenge is a leaf in a
+    /// `MerkleTree` and the RoTs sign the root. Each caller gets the
+    /// attestations over the root w/ an `InclusionProof` for their
+    /// transcript, in the same order as `challenges`.
+    pub fn attest_batch(
+        &self,
+        challenges: &[(&Nonce, &[u8])],
+    ) -> Result<Vec<Vec<Attestation>>, AttestMockError> {
+        let leaves = challenges
+            .iter()
+            .map(|(nonce, user_data)| self.transcript(nonce, user_data))
+            .collect::<Result<Vec<_>, _>>()?;
+        let tree = match MerkleTree::new(leaves) {
+            Some(tree) => tree,
+            None => return Ok(Vec::new()),
+        };
 
-        let mut data = vec![0u8; Log::MAX_SIZE];
-        let len = hubpack::serialize(&mut data, &oxide_log)
-            .map_err(|_| AttestMockError::Serialize)?;
-        data.truncate(len);
+        let attestations = self.sign(tree.root())?;
+
+        Ok((0..challenges.len())
+            .map(|index| {
+                let proof = tree.proof(index);
+                attestations
+                    .iter()
+                    .cloned()
+                    .map(|attestation| Attestation {
+                        proof: proof.clone(),
+                        ..attestation
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+impl AttestationSigner for AttestMock {
+    type Error = AttestMockError;
 
+    /// `propolis` receives the nonce & user data from the caller.
+    /// It then combines this data w/ attributes describing the VM (rootfs,
+    /// instance UUID etc) and gets an attestation over the result from each
+    /// hardware RoT on the platform. If `propolis` holds an `InstanceKey` the
+    /// result is signed by it in place of the Oxide platform RoT.
+    /// The format of each attestation is dependent on the associated `RotType`.
+    /// NOTE: the order of the attestations returned is significant
+    fn attest(
+        &self,
+        nonce: &Nonce,
+        user_data: &[u8],
+    ) -> Result<Vec<Attestation>, Self::Error> {
+        self.sign(&self.transcript(nonce, user_data)?)
+    }
+
+    /// Get all measurement logs from the various RoTs on the platform.
+    fn get_measurement_logs(&self) -> Result<Vec<MeasurementLog>, Self::Error> {
         let mut logs = Vec::new();
-        let rot = RotType::OxideHardware;
-        logs.push(MeasurementLog { rot, data });
+        for (rot, mock) in self.hardware_rots() {
+            logs.push(self.measurement_log(rot, mock)?);
+        }
 
         logs.push(MeasurementLog {
             rot: RotType::OxideInstance,
@@ -185,12 +707,26 @@ impl AttestationSigner for AttestMock {
     }
 
     fn get_cert_chains(&self) -> Result<Vec<CertChain>, Self::Error> {
-        let oxide_platform = CertChain {
-            rot: RotType::OxideHardware,
-            pki_path: self.oxattest_mock.get_certificates()?,
-        };
+        let mut cert_chains = Vec::new();
+        for (rot, mock) in self.hardware_rots() {
+            cert_chains.push(CertChain {
+                rot,
+                pki_path: mock.get_certificates()?,
+            });
+        }
+
+        // the instance key cert is issued by the leaf of the cert chain from
+        // the Oxide platform RoT
+        if let Some(instance_key) = &self.instance_key {
+            let mut pki_path = vec![instance_key.cert().clone()];
+            pki_path.extend(self.oxattest_mock.get_certificates()?);
+            cert_chains.push(CertChain {
+                rot: RotType::OxideInstance,
+                pki_path,
+            });
+        }
 
-        Ok(vec![oxide_platform])
+        Ok(cert_chains)
     }
 }
 
@@ -198,11 +734,12 @@ impl AttestationSigner for AttestMock {
 mod test {
     use crate::*;
     use std::fs;
-    use x509_cert::{Certificate, der::asn1::Utf8StringRef};
+    use verifier::{NonceStoreConfig, VerifierConfig};
+    use x509_cert::Certificate;
 
     /// This module holds `const &str`s with paths to test data generated by
     /// build.rs
-    mod config {
+    pub(crate) mod config {
         include!(concat!(env!("OUT_DIR"), "/config.rs"));
     }
 
@@ -211,21 +748,74 @@ mod test {
     const USER_DATA: [u8; 32] = [0u8; 32];
 
     /// Pull in test data generated by build.rs & create mock RoT-Rs
-    fn setup() -> AttestMock {
+    pub(crate) fn setup() -> AttestMock {
+        setup_with_pki_path(config::SIGNER_PKIPATH)
+    }
+
+    /// Like `setup` but the mock platform RoT presents the cert chain from
+    /// `pki_path`.
+    pub(crate) fn setup_with_pki_path(pki_path: &str) -> AttestMock {
         let oxattest_mock = OxAttestMock::load(
-            config::SIGNER_PKIPATH,
+            pki_path,
             config::LOG,
             config::ATTESTATION_SIGNER,
         )
         .expect("failed to create OxAttestMock from inputs");
 
+        let sp_attest_mock = OxAttestMock::load(
+            config::SP_SIGNER_PKIPATH,
+            config::SP_LOG,
+            config::SP_ATTESTATION_SIGNER,
+        )
+        .expect("failed to create OxAttestMock for SP from inputs");
+
         let instance_cfg = fs::read_to_string(config::VM_INSTANCE_CFG)
             .expect("read ATTEST_INSTANCE_LOG to string");
 
         let instance_cfg: VmInstanceConf = serde_json::from_str(&instance_cfg)
             .expect("parse JSON from mock cfg for instance RoT");
 
-        AttestMock::new(oxattest_mock, instance_cfg)
+        AttestMock::new(oxattest_mock, sp_attest_mock, instance_cfg)
+    }
+
+    /// `VerifierConfig` for the PKI, CoRIM & policy generated by build.rs.
+    pub(crate) fn verifier_config() -> VerifierConfig {
+        VerifierConfig {
+            trust_anchors: vec![config::PKI_ROOT.into()],
+            corims: vec![config::CORIM.into()],
+            crls: Vec::new(),
+            instance_policy: Some(config::INSTANCE_POLICY.into()),
+            freshness_window: 60,
+            nonce_store: NonceStoreConfig::Memory,
+        }
+    }
+
+    /// Create mock RoT-Rs w/ an `InstanceKey` certified by the alias key of
+    /// the Oxide platform RoT.
+    pub(crate) fn setup_instance_key() -> AttestMock {
+        let attest = setup();
+
+        let alias_key = fs::read_to_string(config::ATTESTATION_SIGNER)
+            .expect("read ATTESTATION_SIGNER to string");
+        let alias_key = instance_key::signing_key_from_pem(&alias_key)
+            .expect("parse alias key");
+        let alias_chain = attest
+            .oxattest_mock
+            .get_certificates()
+            .expect("OxAttestMock get_certificates");
+        let platform_log = attest
+            .measurement_log(RotType::OxideHardware, &attest.oxattest_mock)
+            .expect("measurement log from the Oxide platform RoT");
+
+        let instance_key = InstanceKey::generate(
+            &attest.log.uuid,
+            &platform_log,
+            &alias_key,
+            &alias_chain[0],
+        )
+        .expect("generate InstanceKey");
+
+        attest.with_instance_key(instance_key)
     }
 
     #[test]
@@ -237,27 +827,113 @@ mod test {
             match log.rot {
                 RotType::OxideHardware => assert!(!log.data.is_empty()),
                 RotType::OxideInstance => assert!(!log.data.is_empty()),
+                RotType::OxideSp => assert!(!log.data.is_empty()),
+                RotType::Unknown(id) => panic!("log from unknown RoT: {id}"),
             }
         }
     }
 
-    // utility function to get common name from cert subject
-    fn get_cert_cn(cert: &Certificate) -> Option<Utf8StringRef<'_>> {
-        use const_oid::db::rfc4519::COMMON_NAME;
+    #[test]
+    fn decode_measurement_logs() {
+        let attest = setup();
 
-        for elm in cert.tbs_certificate.subject.0.iter() {
-            for atav in elm.0.iter() {
-                if atav.oid == COMMON_NAME {
-                    return Some(
-                        Utf8StringRef::try_from(&atav.value).expect(
-                            "Decode name attribute value to UTF8 string",
-                        ),
-                    );
+        let logs = attest.get_measurement_logs().expect("get_measurement_logs");
+        for log in &logs {
+            match (log.rot, log.decode().expect("decode measurement log")) {
+                (RotType::OxideHardware, DecodedLog::OxideHardware(_)) => (),
+                (RotType::OxideSp, DecodedLog::OxideSp(_)) => (),
+                (RotType::OxideInstance, DecodedLog::OxideInstance(cfg)) => {
+                    assert_eq!(cfg, attest.log)
                 }
+                (rot, log) => panic!("{rot:?} decoded as {log:?}"),
             }
         }
+    }
 
-        None
+    #[test]
+    fn decode_measurement_log_mismatch() {
+        let attest = setup();
+
+        // swap the data between the platform RoT & instance logs so that
+        // each is paired w/ the wrong RotType
+        let mut logs: Vec<_> = attest
+            .get_measurement_logs()
+            .expect("get_measurement_logs")
+            .into_iter()
+            .filter(|log| log.rot != RotType::OxideSp)
+            .collect();
+        let (first, second) = logs.split_at_mut(1);
+        std::mem::swap(&mut first[0].data, &mut second[0].data);
+
+        for log in &logs {
+            match (log.rot, log.decode()) {
+                (
+                    RotType::OxideHardware,
+                    Err(LogDecodeError::NotHubpack(_)),
+                ) => (),
+                (
+                    RotType::OxideInstance,
+                    Err(LogDecodeError::NotJson(_, _)),
+                ) => (),
+                (rot, result) => panic!("{rot:?} decoded as {result:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn rot_type_ids() {
+        for rot in [
+            RotType::OxideHardware,
+            RotType::OxideInstance,
+            RotType::OxideSp,
+        ] {
+            assert_eq!(RotType::from_id(rot.id()), rot);
+            let name = rot.name().expect("known RoT has a name");
+            assert_eq!(name.parse::<RotType>().expect("parse RoT name"), rot);
+            assert_eq!(rot.to_string(), name);
+        }
+
+        let unknown = RotType::from_id(99);
+        assert!(matches!(unknown, RotType::Unknown(u) if u.id() == 99));
+        assert_eq!(unknown.name(), None);
+        // the id of a known RoT is never `Unknown`
+        assert!(!matches!(RotType::from_id(1), RotType::Unknown(_)));
+        assert_eq!("99".parse::<RotType>().expect("parse RoT id"), unknown);
+        assert!("oxide-future".parse::<RotType>().is_err());
+    }
+
+    #[test]
+    fn rot_type_serde() {
+        let json = serde_json::to_string(&[
+            RotType::OxideHardware,
+            RotType::OxideInstance,
+            RotType::from_id(99),
+        ])
+        .expect("serialize RotTypes");
+        assert_eq!(json, "[1,2,99]");
+
+        // a RoT added by a newer provider still deserializes
+        let rots: Vec<RotType> =
+            serde_json::from_str("[1,2,7]").expect("deserialize RotTypes");
+        assert_eq!(
+            rots,
+            [
+                RotType::OxideHardware,
+                RotType::OxideInstance,
+                RotType::from_id(7)
+            ]
+        );
+        // only the numeric wire identifier is accepted
+        assert!(serde_json::from_str::<RotType>("\"oxide-instance\"").is_err());
+
+        let log = MeasurementLog {
+            rot: rots[2],
+            data: Vec::new(),
+        };
+        assert!(matches!(
+            log.decode(),
+            Err(LogDecodeError::UnsupportedRot(RotType::Unknown(u))) if u.id() == 7
+        ));
     }
 
     #[test]
@@ -265,12 +941,15 @@ mod test {
         let attest = setup();
 
         let cert_chains = attest.get_cert_chains().expect("get_cert_chains");
-        assert_eq!(cert_chains.len(), 1);
-        assert!(cert_chains[0].pki_path.len() > 1);
-
-        // the leaf cert CN is defined in test-data/config.kdl
-        let leaf_cn = get_cert_cn(&cert_chains[0].pki_path[0]);
-        assert_eq!(leaf_cn, Some(Utf8StringRef::new("alias").unwrap()));
+        assert_eq!(cert_chains.len(), 2);
+
+        // the leaf cert CNs are defined in test-data/config.kdl
+        for (cert_chain, cn) in cert_chains.iter().zip(["alias", "sp-alias"]) {
+            assert!(cert_chain.pki_path.len() > 1);
+            let leaf_cn =
+                platform_identity::common_name(&cert_chain.pki_path[0]);
+            assert_eq!(leaf_cn, Some(cn));
+        }
     }
 
     #[test]
@@ -282,16 +961,81 @@ mod test {
         // TODO: should be a crypto key
         let user_data = vec![0u8, 1];
 
-        let _ = attest
+        let attestations = attest
             .attest(&nonce, &user_data)
             .expect("AttestMock attest");
+
+        // one attestation per hardware RoT, in the order of the cert chains
+        let cert_chains = attest.get_cert_chains().expect("get_cert_chains");
+        assert_eq!(attestations.len(), cert_chains.len());
+        for (attestation, cert_chain) in attestations.iter().zip(&cert_chains) {
+            assert_eq!(attestation.rot, cert_chain.rot);
+        }
+    }
+
+    #[test]
+    fn attest_batch() {
+        let attest = setup();
+
+        let nonces = [Nonce::from_array([0u8; 32]), Nonce::from_array([1; 32])];
+        let challenges: Vec<_> =
+            nonces.iter().map(|nonce| (nonce, &USER_DATA[..])).collect();
+        let batch = attest.attest_batch(&challenges).expect("attest batch");
+        assert_eq!(batch.len(), nonces.len());
+
+        // each caller gets the same signatures w/ a proof that their
+        // transcript is included in the signed root
+        let mut roots = Vec::new();
+        for (index, (attestations, nonce)) in
+            batch.iter().zip(&nonces).enumerate()
+        {
+            let transcript =
+                attest.transcript(nonce, &USER_DATA).expect("transcript");
+            for (attestation, first) in attestations.iter().zip(&batch[0]) {
+                assert_eq!(attestation.data, first.data);
+                let proof =
+                    attestation.proof.as_ref().expect("inclusion proof");
+                assert_eq!(proof.index, index as u64);
+                roots.push(proof.root(&transcript).expect("root from proof"));
+            }
+        }
+        assert!(roots.windows(2).all(|r| r[0] == r[1]));
+    }
+
+    #[test]
+    fn attest_instance_key() {
+        let attest = setup_instance_key();
+
+        let nonce = Nonce::from_array(NONCE);
+        let attestations = attest
+            .attest(&nonce, &USER_DATA)
+            .expect("AttestMock attest");
+        let rots: Vec<_> = attestations.iter().map(|a| a.rot).collect();
+        assert_eq!(rots, [RotType::OxideSp, RotType::OxideInstance]);
+
+        // the instance cert chain extends the chain from the platform RoT
+        let cert_chains = attest.get_cert_chains().expect("get_cert_chains");
+        let platform = cert_chains
+            .iter()
+            .find(|c| c.rot == RotType::OxideHardware)
+            .expect("cert chain for RotType::OxideHardware");
+        let instance = cert_chains
+            .iter()
+            .find(|c| c.rot == RotType::OxideInstance)
+            .expect("cert chain for RotType::OxideInstance");
+        assert_eq!(instance.pki_path[1..], platform.pki_path[..]);
+        instance_key::verify_cert_signature(
+            &instance.pki_path[0],
+            &instance.pki_path[1],
+        )
+        .expect("instance cert issued by platform alias key");
     }
 
     #[test]
     fn verify_cert_chain() {
         use std::fs;
 
-        let attest = setup();
+        let attest = setup_instance_key();
         let cert_chains = attest.get_cert_chains().expect("get cert chain");
 
         let root_cert = fs::read(config::PKI_ROOT).unwrap_or_else(|e| {
@@ -305,7 +1049,7 @@ mod test {
 
         for cert_chain in cert_chains {
             match cert_chain.rot {
-                RotType::OxideHardware => {
+                RotType::OxideHardware | RotType::OxideSp => {
                     let verified_root = dice_verifier::verify_cert_chain(
                         &cert_chain.pki_path,
                         Some(root_cert.as_ref()),
@@ -313,8 +1057,25 @@ mod test {
                     .expect("verify cert chain");
                     assert_eq!(&root_cert[0], verified_root);
                 }
-                // this RoT doesn't have a cert chain
-                RotType::OxideInstance => assert!(false),
+                // the instance cert is issued by the alias cert from the
+                // Oxide platform RoT, which chains to the root
+                RotType::OxideInstance => {
+                    let platform_path = cert_chain.pki_path[1..].to_vec();
+                    instance_key::verify_cert_signature(
+                        &cert_chain.pki_path[0],
+                        &platform_path[0],
+                    )
+                    .expect("instance cert issued by alias cert");
+                    let verified_root = dice_verifier::verify_cert_chain(
+                        &platform_path,
+                        Some(root_cert.as_ref()),
+                    )
+                    .expect("verify platform cert chain");
+                    assert_eq!(&root_cert[0], verified_root);
+                }
+                RotType::Unknown(id) => {
+                    panic!("cert chain from unknown RoT: {id}")
+                }
             }
         }
     }
@@ -331,22 +1092,16 @@ mod test {
 
         let cert_chains =
             attest.get_cert_chains().expect("AttestMock get_cert_chain");
-        assert_eq!(cert_chains.len(), 1);
-        // signer cert is the leaf
-        let cert = &cert_chains[0].pki_path[0];
 
-        let attestation = attest
+        let attestations = attest
             .attest(&nonce, &USER_DATA)
             .expect("AttestMock attest");
-
-        let (attestation, _): (OxAttestation, _) =
-            hubpack::deserialize(&attestation.data)
-                .expect("deserialize attestation");
+        assert_eq!(attestations.len(), cert_chains.len());
 
         let logs = attest.get_measurement_logs().expect("get_measurement_logs");
 
-        // Reconstruct the 32 bytes passed from `AttestMock` down to the
-        // RotType::OxideHardware:
+        // Reconstruct the 32 bytes passed from `AttestMock` down to each
+        // hardware RoT:
         //
         // The challenger passes OxideInstance RoT 32 byte nonce and a &[u8]
         // that we call `data`. It then combines them as:
@@ -372,30 +1127,38 @@ mod test {
             0: data_digest.into(),
         };
 
-        // get the log from the Oxide platform RoT
-        let oxlog = logs.iter().find_map(|log| {
-            if log.rot == RotType::OxideHardware {
-                Some(log)
-            } else {
-                None
-            }
-        });
-
-        // put log in the form expected by the `verify_attestation` function
-        let (log, _): (Log, _) = if let Some(oxlog) = oxlog {
-            hubpack::deserialize(&oxlog.data)
-                .expect("deserialize hubpacked log")
-        } else {
-            panic!("No measurement log for RotType::OxideHardware");
-        };
-
-        let result = dice_verifier::verify_attestation(
-            &cert,
-            &attestation,
-            &log,
-            &data_digest,
-        );
-        assert!(result.is_ok());
+        // w/o an InstanceKey every hardware RoT attests, in the same order as
+        // the cert chains
+        for (attestation, cert_chain) in attestations.iter().zip(&cert_chains) {
+            assert_eq!(attestation.rot, cert_chain.rot);
+            // signer cert is the leaf
+            let cert = &cert_chain.pki_path[0];
+
+            let (attestation_data, _): (OxAttestation, _) =
+                hubpack::deserialize(&attestation.data)
+                    .expect("deserialize attestation");
+
+            // get the log from the same RoT in the form expected by the
+            // `verify_attestation` function
+            let log = logs
+                .iter()
+                .find(|log| log.rot == attestation.rot)
+                .expect("No measurement log for hardware RoT");
+            let log = match log.decode().expect("decode measurement log") {
+                DecodedLog::OxideHardware(log) | DecodedLog::OxideSp(log) => {
+                    log
+                }
+                DecodedLog::OxideInstance(_) => panic!("not a hardware RoT"),
+            };
+
+            let result = dice_verifier::verify_attestation(
+                cert,
+                &attestation_data,
+                &log,
+                &data_digest,
+            );
+            assert!(result.is_ok(), "{:?}", attestation.rot);
+        }
     }
 
     #[test]
@@ -410,21 +1173,10 @@ mod test {
             ReferenceMeasurements::try_from(std::slice::from_ref(&corim))
                 .expect("Reference integrity measurements from file");
 
-        // get cert chain required to reconstruct the collection of measurements
+        // get cert chains required to reconstruct the collection of
+        // measurements for each hardware RoT
         let cert_chains =
             attest.get_cert_chains().expect("AttestMock get_cert_chain");
-        let cert_chain = cert_chains.iter().find_map(|cert_chain| {
-            if cert_chain.rot == RotType::OxideHardware {
-                Some(cert_chain)
-            } else {
-                None
-            }
-        });
-        let cert_chain = if let Some(cert_chain) = cert_chain {
-            cert_chain
-        } else {
-            panic!("No cert chain for RotType::OxideHardware");
-        };
 
         // construct a `VmInstanceConf` from test data
         // this is our reference for appraising the log produced by the
@@ -436,16 +1188,19 @@ mod test {
 
         let logs = attest.get_measurement_logs().expect("get_measurement_logs");
         for log in &logs {
-            match log.rot {
-                RotType::OxideHardware => {
-                    // use dice-verifier crate to use the RIMs to appraise the
-                    // log from the OxideHardware RoT
-                    let (log, _): (Log, _) = hubpack::deserialize(&log.data)
-                        .expect("deserialize hubpacked log");
+            match log.decode().expect("decode measurement log") {
+                DecodedLog::OxideHardware(oxlog)
+                | DecodedLog::OxideSp(oxlog) => {
+                    let cert_chain = cert_chains
+                        .iter()
+                        .find(|cert_chain| cert_chain.rot == log.rot)
+                        .expect("No cert chain for hardware RoT");
 
+                    // use dice-verifier crate to use the RIMs to appraise the
+                    // log from the hardware RoT
                     let measurements = MeasurementSet::from_artifacts(
                         &cert_chain.pki_path,
-                        &log,
+                        &oxlog,
                     )
                     .expect("MeasurementSet from PkiPath and Log");
 
@@ -455,18 +1210,91 @@ mod test {
                     );
                     assert!(result.is_ok());
                 }
-                RotType::OxideInstance => {
+                DecodedLog::OxideInstance(instance_cfg) => {
                     // compare log / config description from the OxideInstance
                     // RoT to the reference from the config reference
-                    let instance_cfg = std::str::from_utf8(&log.data)
-                        .expect("utf8 from log data");
-                    let instance_cfg: VmInstanceConf =
-                        serde_json::from_str(instance_cfg)
-                            .expect("parse JSON from log data");
-
                     assert_eq!(instance_rim, instance_cfg);
                 }
             }
         }
     }
+
+    /// Write the seed corpus for the fuzz targets in `fuzz/` from the
+    /// fixtures generated by build.rs.
+    #[test]
+    #[ignore = "writes the fuzz seed corpus to fuzz/corpus"]
+    fn fuzz_seed_corpus() {
+        use std::path::PathBuf;
+        use x509_cert::der::{Encode, EncodePem, pem::LineEnding};
+
+        let corpus = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("fuzz")
+            .join("corpus");
+        let write = |target: &str, name: &str, data: &[u8]| {
+            let dir = corpus.join(target);
+            fs::create_dir_all(&dir).expect("create corpus dir");
+            fs::write(dir.join(name), data).expect("write corpus file");
+        };
+
+        let trust_anchor =
+            fs::read_to_string(config::PKI_ROOT).expect("read PKI_ROOT");
+        let nonce = Nonce::from_array(NONCE);
+
+        for (name, attest) in
+            [("mock", setup()), ("instance-key", setup_instance_key())]
+        {
+            let evidence =
+                verifier::Evidence::collect(&attest, &nonce, &USER_DATA)
+                    .expect("evidence");
+
+            // the 32 bytes signed by the RoTs:
+            // `sha256(instance_log | nonce | user_data)`
+            let mut signed = Sha256::new();
+            for log in &evidence.logs {
+                if log.rot == RotType::OxideInstance {
+                    signed.update(&log.data);
+                }
+            }
+            signed.update(&nonce);
+            signed.update(USER_DATA);
+            let input = serde_json::json!({
+                "attestations": evidence.attestations,
+                "cert-chains": evidence.cert_chains,
+                "logs": evidence.logs,
+                "data": hex::encode(signed.finalize()),
+            });
+            let input = serde_json::to_vec_pretty(&input).expect("JSON input");
+            write("attestation", &format!("{name}.json"), &input);
+            for log in &evidence.logs {
+                let file = format!("{name}-{}", log.rot);
+                match log.rot {
+                    RotType::OxideInstance => {
+                        write("instance_conf", &file, &log.data)
+                    }
+                    _ => write("measurement_log", &file, &log.data),
+                }
+            }
+            for cert_chain in &evidence.cert_chains {
+                let file = format!("{name}-{}", cert_chain.rot);
+                let der = cert_chain.pki_path.to_der().expect("PkiPath to DER");
+                write("cert_chain", &format!("{file}.der"), &der);
+                let pem = cert_chain
+                    .pki_path
+                    .iter()
+                    .map(|cert| cert.to_pem(LineEnding::LF))
+                    .collect::<Result<String, _>>()
+                    .expect("PkiPath to PEM");
+                write("cert_chain", &format!("{file}.pem"), pem.as_bytes());
+            }
+
+            let input = serde_json::json!({
+                "trust-anchor": trust_anchor,
+                "nonce": hex::encode(NONCE),
+                "user-data": hex::encode(USER_DATA),
+                "evidence": evidence,
+            });
+            let input = serde_json::to_vec_pretty(&input).expect("JSON input");
+            write("verify", &format!("{name}.json"), &input);
+        }
+    }
 }
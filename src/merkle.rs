@@ -0,0 +1,222 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Errors returned when an `InclusionProof` is malformed.
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum MerkleError {
+    #[error("leaf index {index} is outside a tree of {leaf_count} leaves")]
+    Index { index: u64, leaf_count: u64 },
+    #[error("inclusion proof has the wrong number of hashes")]
+    ProofLength,
+}
+
+/// The hash of a leaf, domain separated from interior nodes as described in
+/// RFC 9162 §2.1.1.
+fn leaf_hash(leaf: &[u8; 32]) -> [u8; 32] {
+    let mut digest = Sha256::new();
+    digest.update([0x00]);
+    digest.update(leaf);
+    digest.finalize().into()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut digest = Sha256::new();
+    digest.update([0x01]);
+    digest.update(left);
+    digest.update(right);
+    digest.finalize().into()
+}
+
+/// The largest power of 2 less than `n`, `n` must be > 1.
+fn split(n: usize) -> usize {
+    1 << (usize::BITS - (n - 1).leading_zeros() - 1)
+}
+
+/// The hash of the tree over `leaves`, `leaves` must not be empty.
+fn tree_hash(leaves: &[[u8; 32]]) -> [u8; 32] {
+    match leaves.len() {
+        1 => leaf_hash(&leaves[0]),
+        n => {
+            let (left, right) = leaves.split_at(split(n));
+            node_hash(&tree_hash(left), &tree_hash(right))
+        }
+    }
+}
+
+/// The audit path for the leaf at `index` from RFC 9162 §2.1.3.1.
+fn path(index: usize, leaves: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    if leaves.len() == 1 {
+        return Vec::new();
+    }
+
+    let (left, right) = leaves.split_at(split(leaves.len()));
+    if index < left.len() {
+        let mut path = path(index, left);
+        path.push(tree_hash(right));
+        path
+    } else {
+        let mut path = path(index - left.len(), right);
+        path.push(tree_hash(left));
+        path
+    }
+}
+
+/// A Merkle tree over the 32 byte transcripts from a batch of callers. The
+/// root is signed once and each caller gets an `InclusionProof` for their
+/// transcript.
+pub struct MerkleTree {
+    leaves: Vec<[u8; 32]>,
+    root: [u8; 32],
+}
+
+impl MerkleTree {
+    /// Build a tree over `leaves`. Returns `None` if `leaves` is empty.
+    pub fn new(leaves: Vec<[u8; 32]>) -> Option<Self> {
+        if leaves.is_empty() {
+            return None;
+        }
+        let root = tree_hash(&leaves);
+
+        Some(Self { leaves, root })
+    }
+
+    pub fn root(&self) -> &[u8; 32] {
+        &self.root
+    }
+
+    /// Get the inclusion proof for the leaf at `index`.
+    pub fn proof(&self, index: usize) -> Option<InclusionProof> {
+        if index >= self.leaves.len() {
+            return None;
+        }
+
+        Some(InclusionProof {
+            index: index as u64,
+            leaf_count: self.leaves.len() as u64,
+            path: path(index, &self.leaves),
+        })
+    }
+}
+
+/// Proof that a leaf is included in a `MerkleTree` of `leaf_count` leaves.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InclusionProof {
+    pub index: u64,
+    pub leaf_count: u64,
+    #[serde(with = "hex_path")]
+    pub path: Vec<[u8; 32]>,
+}
+
+/// Serialize the audit path as a list of hex strings.
+mod hex_path {
+    use serde::{Deserialize, Deserializer, Serializer, de};
+
+    pub fn serialize<S: Serializer>(
+        path: &[[u8; 32]],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(path.iter().map(hex::encode))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<[u8; 32]>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|hash| {
+                let mut bytes = [0u8; 32];
+                hex::decode_to_slice(hash, &mut bytes)
+                    .map_err(de::Error::custom)?;
+                Ok(bytes)
+            })
+            .collect()
+    }
+}
+
+impl InclusionProof {
+    /// Compute the root of the tree from `leaf` & the audit path per
+    /// RFC 9162 §2.1.3.2. The proof is valid if the result is the root that
+    /// was signed.
+    pub fn root(&self, leaf: &[u8; 32]) -> Result<[u8; 32], MerkleError> {
+        if self.index >= self.leaf_count {
+            return Err(MerkleError::Index {
+                index: self.index,
+                leaf_count: self.leaf_count,
+            });
+        }
+
+        let mut f_n = self.index;
+        let mut s_n = self.leaf_count - 1;
+        let mut root = leaf_hash(leaf);
+        for hash in &self.path {
+            if s_n == 0 {
+                return Err(MerkleError::ProofLength);
+            }
+            if f_n & 1 == 1 || f_n == s_n {
+                root = node_hash(hash, &root);
+                while f_n & 1 == 0 && f_n != 0 {
+                    f_n >>= 1;
+                    s_n >>= 1;
+                }
+            } else {
+                root = node_hash(&root, hash);
+            }
+            f_n >>= 1;
+            s_n >>= 1;
+        }
+
+        if s_n != 0 {
+            return Err(MerkleError::ProofLength);
+        }
+
+        Ok(root)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn leaves(count: u8) -> Vec<[u8; 32]> {
+        (0..count).map(|i| [i; 32]).collect()
+    }
+
+    #[test]
+    fn inclusion_proofs() {
+        for count in 1..=9 {
+            let tree = MerkleTree::new(leaves(count)).expect("MerkleTree");
+            for (index, leaf) in leaves(count).iter().enumerate() {
+                let proof = tree.proof(index).expect("inclusion proof");
+                assert_eq!(proof.root(leaf), Ok(*tree.root()));
+
+                // the proof doesn't hold for any other leaf
+                let other = [0xff; 32];
+                assert_ne!(proof.root(&other), Ok(*tree.root()));
+            }
+            assert!(tree.proof(count.into()).is_none());
+        }
+    }
+
+    #[test]
+    fn malformed_proof() {
+        let tree = MerkleTree::new(leaves(5)).expect("MerkleTree");
+        let leaf = [2u8; 32];
+
+        let mut proof = tree.proof(2).expect("inclusion proof");
+        proof.path.pop();
+        assert_eq!(proof.root(&leaf), Err(MerkleError::ProofLength));
+
+        let mut proof = tree.proof(2).expect("inclusion proof");
+        proof.path.push([0u8; 32]);
+        assert_eq!(proof.root(&leaf), Err(MerkleError::ProofLength));
+
+        let mut proof = tree.proof(2).expect("inclusion proof");
+        proof.index = 5;
+        assert!(matches!(proof.root(&leaf), Err(MerkleError::Index { .. })));
+    }
+}
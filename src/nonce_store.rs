@@ -0,0 +1,302 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::Nonce;
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Errors returned by a `NonceStore`.
+#[derive(Debug, thiserror::Error)]
+pub enum NonceStoreError {
+    #[error("failed to access nonce store file")]
+    Io(#[from] std::io::Error),
+    #[error("failed to (de)serialize nonce store file")]
+    Serialize(#[from] serde_json::Error),
+}
+
+/// The verifier records each nonce it issues to a challenger along w/ the
+/// time it was issued. When evidence is presented for verification the
+/// nonce is removed from the store, ensuring that each nonce is used at most
+/// once, and the issue time is used to check freshness.
+pub trait NonceStore: Send + Sync {
+    /// Record a nonce & the time it was issued.
+    fn insert(
+        &self,
+        nonce: &Nonce,
+        issued: SystemTime,
+    ) -> Result<(), NonceStoreError>;
+
+    /// Remove the nonce from the store returning the time it was issued, or
+    /// `None` if it was never issued (or has already been used).
+    fn take(
+        &self,
+        nonce: &Nonce,
+    ) -> Result<Option<SystemTime>, NonceStoreError>;
+
+    /// Forget all nonces issued before `before`.
+    fn prune(&self, before: SystemTime) -> Result<(), NonceStoreError>;
+}
+
+/// A `NonceStore` that keeps nonces in memory. Nonces are lost when the
+/// verifier exits.
+#[derive(Default)]
+pub struct MemoryNonceStore {
+    nonces: Mutex<HashMap<Nonce, SystemTime>>,
+}
+
+impl NonceStore for MemoryNonceStore {
+    fn insert(
+        &self,
+        nonce: &Nonce,
+        issued: SystemTime,
+    ) -> Result<(), NonceStoreError> {
+        self.nonces
+            .lock()
+            .expect("nonce store lock poisoned")
+            .insert(nonce.clone(), issued);
+
+        Ok(())
+    }
+
+    fn take(
+        &self,
+        nonce: &Nonce,
+    ) -> Result<Option<SystemTime>, NonceStoreError> {
+        Ok(self
+            .nonces
+            .lock()
+            .expect("nonce store lock poisoned")
+            .remove(nonce))
+    }
+
+    fn prune(&self, before: SystemTime) -> Result<(), NonceStoreError> {
+        self.nonces
+            .lock()
+            .expect("nonce store lock poisoned")
+            .retain(|_, issued| *issued >= before);
+
+        Ok(())
+    }
+}
+
+/// A `NonceStore` backed by a JSON file mapping the hex encoded nonce to the
+/// time it was issued in seconds since the unix epoch. The file is replaced
+/// on each change so that issued nonces survive a restart of the verifier:
+/// the new contents are written to `<path>.tmp` & renamed into place so a
+/// crash never leaves a partially written store. Each read-modify-write
+/// holds an advisory lock on `<path>.lock` so verifiers in separate
+/// processes may share the store.
+pub struct FileNonceStore {
+    path: PathBuf,
+}
+
+/// An exclusive advisory lock (`flock`) on a file, released when the file is
+/// closed on drop.
+struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    fn acquire(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        loop {
+            // SAFETY: flock on a valid fd has no memory safety requirements
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+                return Ok(Self { _file: file });
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
+
+impl FileNonceStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// `path` w/ `suffix` appended to the file name.
+    fn sibling(&self, suffix: &str) -> PathBuf {
+        let mut path = OsString::from(&self.path);
+        path.push(suffix);
+        path.into()
+    }
+
+    fn lock(&self) -> Result<FileLock, NonceStoreError> {
+        Ok(FileLock::acquire(&self.sibling(".lock"))?)
+    }
+
+    fn load(&self) -> Result<BTreeMap<String, u64>, NonceStoreError> {
+        match fs::read(&self.path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Ok(BTreeMap::new())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn store(
+        &self,
+        nonces: &BTreeMap<String, u64>,
+    ) -> Result<(), NonceStoreError> {
+        let tmp = self.sibling(".tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&serde_json::to_vec(nonces)?)?;
+        file.sync_all()?;
+
+        Ok(fs::rename(&tmp, &self.path)?)
+    }
+}
+
+fn to_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl NonceStore for FileNonceStore {
+    fn insert(
+        &self,
+        nonce: &Nonce,
+        issued: SystemTime,
+    ) -> Result<(), NonceStoreError> {
+        let _lock = self.lock()?;
+        let mut nonces = self.load()?;
+        nonces.insert(hex::encode(nonce), to_secs(issued));
+
+        self.store(&nonces)
+    }
+
+    fn take(
+        &self,
+        nonce: &Nonce,
+    ) -> Result<Option<SystemTime>, NonceStoreError> {
+        let _lock = self.lock()?;
+        let mut nonces = self.load()?;
+        let issued = match nonces.remove(&hex::encode(nonce)) {
+            Some(issued) => issued,
+            None => return Ok(None),
+        };
+        self.store(&nonces)?;
+
+        Ok(Some(UNIX_EPOCH + Duration::from_secs(issued)))
+    }
+
+    fn prune(&self, before: SystemTime) -> Result<(), NonceStoreError> {
+        let _lock = self.lock()?;
+        let mut nonces = self.load()?;
+        let before = to_secs(before);
+        nonces.retain(|nonce, issued| {
+            // drop anything we can't parse along w/ the expired entries
+            *issued >= before && hex::decode(nonce).is_ok_and(|n| n.len() == 32)
+        });
+
+        self.store(&nonces)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn nonce_store(name: &str) -> FileNonceStore {
+        let path = std::env::temp_dir()
+            .join(format!("nonce-store-{name}-{}.json", std::process::id()));
+        let store = FileNonceStore::new(path);
+        remove(&store);
+
+        store
+    }
+
+    fn remove(store: &FileNonceStore) {
+        for path in [store.path.clone(), store.sibling(".lock")] {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn file_insert_take() {
+        let store = nonce_store("insert-take");
+        let nonce = Nonce::from_array([1u8; 32]);
+        let issued = UNIX_EPOCH + Duration::from_secs(1000);
+        store.insert(&nonce, issued).expect("insert nonce");
+
+        // issued nonces survive a restart of the verifier
+        let store = FileNonceStore::new(store.path.clone());
+        assert_eq!(store.take(&nonce).expect("take nonce"), Some(issued));
+
+        // each nonce can only be taken once
+        let store = FileNonceStore::new(store.path.clone());
+        assert_eq!(store.take(&nonce).expect("take nonce"), None);
+
+        remove(&store);
+    }
+
+    #[test]
+    fn file_prune() {
+        let store = nonce_store("prune");
+        let old = Nonce::from_array([1u8; 32]);
+        let new = Nonce::from_array([2u8; 32]);
+        store
+            .insert(&old, UNIX_EPOCH + Duration::from_secs(1000))
+            .expect("insert old nonce");
+        store
+            .insert(&new, UNIX_EPOCH + Duration::from_secs(2000))
+            .expect("insert new nonce");
+        store
+            .prune(UNIX_EPOCH + Duration::from_secs(1500))
+            .expect("prune nonces");
+
+        let store = FileNonceStore::new(store.path.clone());
+        assert_eq!(store.take(&old).expect("take old nonce"), None);
+        assert_eq!(
+            store.take(&new).expect("take new nonce"),
+            Some(UNIX_EPOCH + Duration::from_secs(2000))
+        );
+
+        remove(&store);
+    }
+
+    #[test]
+    fn file_shared_take() {
+        let store = nonce_store("shared-take");
+        let nonce = Nonce::from_array([3u8; 32]);
+        let issued = UNIX_EPOCH + Duration::from_secs(1000);
+        store.insert(&nonce, issued).expect("insert nonce");
+
+        // verifiers sharing the store each take the nonce at most once
+        let taken: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    let store = FileNonceStore::new(store.path.clone());
+                    let nonce = &nonce;
+                    scope.spawn(move || store.take(nonce).expect("take nonce"))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|h| h.join().expect("join"))
+                .collect()
+        });
+        assert_eq!(taken, [issued]);
+        assert!(!store.sibling(".tmp").exists());
+
+        remove(&store);
+    }
+}
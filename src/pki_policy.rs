@@ -0,0 +1,281 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The constraints that the platform PKI places on each cert in a DICE cert
+//! chain. Verifying the signatures along a chain shows that it was issued
+//! by a trust anchor, these checks show that each cert was issued for the
+//! role it plays in the chain:
+//! - every cert asserts the `tcg-dice-kp-attest-init` certificate policy
+//! - the leaf (the alias cert) is not a CA & its key may be used for
+//!   digital signatures
+//! - every other cert is a CA w/in its path length constraint, its key may
+//!   be used to sign certs, and it asserts the `tcg-dice-kp-eca`,
+//!   `tcg-dice-kp-identity-init` & `oana-platform-identity` policies: it's
+//!   one of the identity CAs for the platform (the DeviceId & above)
+//!
+//! The cert for an `InstanceKey` is the exception: it's issued by the alias
+//! cert, which isn't a CA, and asserts no policies.
+
+use const_oid::ObjectIdentifier;
+use x509_cert::{
+    Certificate,
+    ext::pkix::{BasicConstraints, CertificatePolicies, KeyUsage, KeyUsages},
+};
+
+/// The TCG DICE `tcg-dice-kp-attest-init` certificate policy: the key may
+/// be used to attest to the initial state of the TCB.
+pub const TCG_DICE_KP_ATTEST_INIT: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("2.23.133.5.4.100.8");
+
+/// The TCG DICE `tcg-dice-kp-identity-init` certificate policy: the key is
+/// an identity for the initial state of the TCB.
+pub const TCG_DICE_KP_IDENTITY_INIT: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("2.23.133.5.4.100.6");
+
+/// The TCG DICE `tcg-dice-kp-eca` certificate policy: the key belongs to an
+/// embedded CA.
+pub const TCG_DICE_KP_ECA: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("2.23.133.5.4.100.12");
+
+/// The Oxide `oana-platform-identity` certificate policy: the key is part
+/// of the identity of an Oxide platform.
+pub const OANA_PLATFORM_IDENTITY: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.3.6.1.4.1.57551.1.3");
+
+#[derive(Debug, thiserror::Error)]
+pub enum PkiPolicyError {
+    #[error("empty cert chain")]
+    Empty,
+    #[error("failed to decode extension from cert {0}")]
+    Extension(usize, #[source] der::Error),
+    #[error("cert {0} is a CA but it's the leaf of the chain")]
+    CaLeaf(usize),
+    #[error("cert {0} is not a CA but it issued the previous cert")]
+    NotCa(usize),
+    #[error("cert {index} is outside of its path length constraint {limit}")]
+    PathLength { index: usize, limit: u8 },
+    #[error("cert {0} has no key usage extension")]
+    NoKeyUsage(usize),
+    #[error("cert {index} key usage doesn't allow {usage}")]
+    KeyUsage { index: usize, usage: &'static str },
+    #[error("cert {index} doesn't assert certificate policy {policy}")]
+    Policy {
+        index: usize,
+        policy: ObjectIdentifier,
+    },
+}
+
+/// Check the constraints on each cert in `pki_path`, leaf first. The trust
+/// anchor isn't expected to be in the chain.
+pub fn check_pki_path(pki_path: &[Certificate]) -> Result<(), PkiPolicyError> {
+    if pki_path.is_empty() {
+        return Err(PkiPolicyError::Empty);
+    }
+
+    for (index, cert) in pki_path.iter().enumerate() {
+        let is_ca = basic_constraints(index, cert)?
+            .map(|bc| (bc.ca, bc.path_len_constraint));
+        match (index, is_ca) {
+            (0, Some((true, _))) => return Err(PkiPolicyError::CaLeaf(index)),
+            (0, _) => {
+                check_key_usage(index, cert, KeyUsages::DigitalSignature)?
+            }
+            (_, Some((true, limit))) => {
+                // the number of CAs between this cert & the leaf
+                match limit {
+                    Some(limit) if usize::from(limit) < index - 1 => {
+                        return Err(PkiPolicyError::PathLength {
+                            index,
+                            limit,
+                        });
+                    }
+                    _ => (),
+                }
+                check_key_usage(index, cert, KeyUsages::KeyCertSign)?;
+                check_policy(index, cert, TCG_DICE_KP_ECA)?;
+                check_policy(index, cert, TCG_DICE_KP_IDENTITY_INIT)?;
+                check_policy(index, cert, OANA_PLATFORM_IDENTITY)?;
+            }
+            (_, _) => return Err(PkiPolicyError::NotCa(index)),
+        }
+        check_policy(index, cert, TCG_DICE_KP_ATTEST_INIT)?;
+    }
+
+    Ok(())
+}
+
+/// Check the constraints on the cert for an `InstanceKey`: it's not a CA &
+/// its key may be used for digital signatures.
+pub fn check_instance_cert(cert: &Certificate) -> Result<(), PkiPolicyError> {
+    if basic_constraints(0, cert)?.is_some_and(|bc| bc.ca) {
+        return Err(PkiPolicyError::CaLeaf(0));
+    }
+
+    check_key_usage(0, cert, KeyUsages::DigitalSignature)
+}
+
+fn basic_constraints(
+    index: usize,
+    cert: &Certificate,
+) -> Result<Option<BasicConstraints>, PkiPolicyError> {
+    Ok(cert
+        .tbs_certificate
+        .get::<BasicConstraints>()
+        .map_err(|e| PkiPolicyError::Extension(index, e))?
+        .map(|(_, bc)| bc))
+}
+
+fn check_key_usage(
+    index: usize,
+    cert: &Certificate,
+    usage: KeyUsages,
+) -> Result<(), PkiPolicyError> {
+    let (_, key_usage) = cert
+        .tbs_certificate
+        .get::<KeyUsage>()
+        .map_err(|e| PkiPolicyError::Extension(index, e))?
+        .ok_or(PkiPolicyError::NoKeyUsage(index))?;
+
+    if key_usage.0.contains(usage) {
+        return Ok(());
+    }
+    let usage = match usage {
+        KeyUsages::DigitalSignature => "digital signatures",
+        KeyUsages::KeyCertSign => "cert signing",
+        _ => "the required usage",
+    };
+
+    Err(PkiPolicyError::KeyUsage { index, usage })
+}
+
+fn check_policy(
+    index: usize,
+    cert: &Certificate,
+    policy: ObjectIdentifier,
+) -> Result<(), PkiPolicyError> {
+    let asserted = cert
+        .tbs_certificate
+        .get::<CertificatePolicies>()
+        .map_err(|e| PkiPolicyError::Extension(index, e))?
+        .is_some_and(|(_, policies)| {
+            policies.0.iter().any(|p| p.policy_identifier == policy)
+        });
+
+    match asserted {
+        true => Ok(()),
+        false => Err(PkiPolicyError::Policy { index, policy }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::config;
+    use const_oid::AssociatedOid;
+    use der::{Encode, asn1::OctetString};
+    use std::fs;
+
+    fn load(path: &str) -> Vec<Certificate> {
+        let pem = fs::read(path).expect("read cert chain");
+        Certificate::load_pem_chain(&pem).expect("parse cert chain")
+    }
+
+    #[test]
+    fn platform_pki_path() {
+        check_pki_path(&load(config::SIGNER_PKIPATH))
+            .expect("check platform RoT cert chain");
+        check_pki_path(&load(config::SP_SIGNER_PKIPATH))
+            .expect("check SP RoT cert chain");
+    }
+
+    #[test]
+    fn no_policy() {
+        let result = check_pki_path(&load(config::NO_POLICY_PKIPATH));
+        assert!(matches!(
+            result,
+            Err(PkiPolicyError::Policy {
+                index: 0,
+                policy: TCG_DICE_KP_ATTEST_INIT,
+            })
+        ));
+    }
+
+    #[test]
+    fn no_identity_policy() {
+        // the device id cert asserts every policy but the one under test
+        for (path, policy) in [
+            (config::NO_PLATFORM_IDENTITY_PKIPATH, OANA_PLATFORM_IDENTITY),
+            (config::NO_IDENTITY_INIT_PKIPATH, TCG_DICE_KP_IDENTITY_INIT),
+        ] {
+            let result = check_pki_path(&load(path));
+            match result {
+                Err(PkiPolicyError::Policy {
+                    index: 1,
+                    policy: p,
+                }) => {
+                    assert_eq!(p, policy)
+                }
+                _ => panic!("expected device id w/o policy {policy}"),
+            }
+        }
+    }
+
+    #[test]
+    fn bad_key_usage() {
+        let result = check_pki_path(&load(config::BAD_KEY_USAGE_PKIPATH));
+        assert!(matches!(
+            result,
+            Err(PkiPolicyError::KeyUsage { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn ca_constraints() {
+        let pki_path = load(config::SIGNER_PKIPATH);
+
+        // the device id cert is a CA
+        let result = check_pki_path(&pki_path[1..]);
+        assert!(matches!(result, Err(PkiPolicyError::CaLeaf(0))));
+
+        // the alias cert isn't
+        let result =
+            check_pki_path(&[pki_path[0].clone(), pki_path[0].clone()]);
+        assert!(matches!(result, Err(PkiPolicyError::NotCa(1))));
+    }
+
+    /// Constrain the CA `cert` to `limit` CAs below it. Signatures aren't
+    /// checked by `check_pki_path`.
+    fn set_path_len(cert: &mut Certificate, limit: u8) {
+        let constraints = BasicConstraints {
+            ca: true,
+            path_len_constraint: Some(limit),
+        };
+        let ext = cert
+            .tbs_certificate
+            .extensions
+            .iter_mut()
+            .flatten()
+            .find(|ext| ext.extn_id == BasicConstraints::OID)
+            .expect("basic constraints extension");
+        ext.extn_value =
+            OctetString::new(constraints.to_der().expect("encode"))
+                .expect("OctetString");
+    }
+
+    #[test]
+    fn path_length() {
+        // the device id may issue the alias cert
+        let mut pki_path = load(config::SIGNER_PKIPATH);
+        set_path_len(&mut pki_path[1], 0);
+        check_pki_path(&pki_path).expect("device id w/ path length 0");
+
+        // but the platform id may not issue the device id
+        set_path_len(&mut pki_path[2], 0);
+        let result = check_pki_path(&pki_path);
+        assert!(matches!(
+            result,
+            Err(PkiPolicyError::PathLength { index: 2, limit: 0 })
+        ));
+    }
+}
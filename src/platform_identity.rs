@@ -0,0 +1,196 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The identity of the platform (sled) that produced a DICE cert chain.
+//! Each platform has a platform identity cert issued during manufacturing
+//! w/ its platform ID as the subject CN, e.g.
+//! `PDV2:PPP-PPPPPPP:RRR:00000000001`: the platform ID format version,
+//! part number, revision & serial number separated by ':'. The platform
+//! identity cert issues the DeviceId cert, which issues the alias cert at
+//! the leaf of the chain that identifies the RoT on that platform.
+
+use const_oid::db::rfc4519::COMMON_NAME;
+use der::asn1::{PrintableStringRef, Utf8StringRef};
+use serde::Serialize;
+use std::fmt;
+use x509_cert::Certificate;
+
+/// The platform ID format versions produced by manufacturing.
+const PLATFORM_ID_PREFIXES: [&str; 2] = ["PDV1", "PDV2"];
+
+/// The position of the platform identity cert in a DICE cert chain, leaf
+/// first: the alias cert, the DeviceId cert & then the cert that issued it.
+pub const PLATFORM_ID_INDEX: usize = 2;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PlatformIdentityError {
+    #[error("empty cert chain")]
+    Empty,
+    #[error("cert {0} has no UTF8 or printable string CN")]
+    CommonName(usize),
+    #[error("no platform identity cert in cert chain")]
+    NoIdentityCert,
+    #[error("platform identity cert CN isn't a platform ID: {0}")]
+    PlatformId(String),
+}
+
+/// The platform ID from the platform identity cert & the CN of the alias
+/// cert.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PlatformIdentity {
+    /// The full platform ID.
+    pub platform_id: String,
+    pub part: String,
+    pub revision: String,
+    pub serial: String,
+    /// The CN of the alias cert.
+    pub alias: String,
+}
+
+impl PlatformIdentity {
+    /// Extract the platform identity from `pki_path`, a DICE cert chain w/
+    /// the alias cert as the leaf. The platform identity cert is always at
+    /// `PLATFORM_ID_INDEX`, a platform ID in the CN of any other cert is
+    /// ignored.
+    pub fn from_pki_path(
+        pki_path: &[Certificate],
+    ) -> Result<Self, PlatformIdentityError> {
+        let alias = pki_path.first().ok_or(PlatformIdentityError::Empty)?;
+        let alias =
+            common_name(alias).ok_or(PlatformIdentityError::CommonName(0))?;
+        let identity = pki_path
+            .get(PLATFORM_ID_INDEX)
+            .ok_or(PlatformIdentityError::NoIdentityCert)?;
+        let platform_id = common_name(identity)
+            .ok_or(PlatformIdentityError::CommonName(PLATFORM_ID_INDEX))?;
+
+        Self::parse(platform_id, alias).ok_or_else(|| {
+            PlatformIdentityError::PlatformId(platform_id.to_string())
+        })
+    }
+
+    fn parse(platform_id: &str, alias: &str) -> Option<Self> {
+        let mut fields = platform_id.split(':');
+        let prefix = fields.next()?;
+        if !PLATFORM_ID_PREFIXES.contains(&prefix) {
+            return None;
+        }
+        let (part, revision, serial) =
+            (fields.next()?, fields.next()?, fields.next()?);
+        if fields.next().is_some() || serial.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            platform_id: platform_id.to_string(),
+            part: part.to_string(),
+            revision: revision.to_string(),
+            serial: serial.to_string(),
+            alias: alias.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for PlatformIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "platform {} alias {}", self.platform_id, self.alias)
+    }
+}
+
+/// The first CN in the subject of `cert`.
+pub(crate) fn common_name(cert: &Certificate) -> Option<&str> {
+    let atav = cert
+        .tbs_certificate
+        .subject
+        .0
+        .iter()
+        .flat_map(|rdn| rdn.0.iter())
+        .find(|atav| atav.oid == COMMON_NAME)?;
+
+    match Utf8StringRef::try_from(&atav.value) {
+        Ok(cn) => Some(cn.as_str()),
+        Err(_) => PrintableStringRef::try_from(&atav.value)
+            .ok()
+            .map(|cn| cn.as_str()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::config;
+    use std::fs;
+
+    fn load(path: &str) -> Vec<Certificate> {
+        let pem = fs::read(path).expect("read cert chain");
+        Certificate::load_pem_chain(&pem).expect("parse cert chain")
+    }
+
+    #[test]
+    fn platform_identity() {
+        // the platform identity cert is defined in test-data/config.kdl
+        for (path, alias) in [
+            (config::SIGNER_PKIPATH, "alias"),
+            (config::SP_SIGNER_PKIPATH, "sp-alias"),
+        ] {
+            let identity = PlatformIdentity::from_pki_path(&load(path))
+                .expect("platform identity from cert chain");
+            assert_eq!(
+                identity,
+                PlatformIdentity {
+                    platform_id: "PDV2:PPP-PPPPPPP:RRR:00000000001".to_string(),
+                    part: "PPP-PPPPPPP".to_string(),
+                    revision: "RRR".to_string(),
+                    serial: "00000000001".to_string(),
+                    alias: alias.to_string(),
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn no_identity_cert() {
+        let result =
+            PlatformIdentity::from_pki_path(&load(config::FOREIGN_PKIPATH));
+        assert!(matches!(result, Err(PlatformIdentityError::NoIdentityCert)));
+
+        let result = PlatformIdentity::from_pki_path(&[]);
+        assert!(matches!(result, Err(PlatformIdentityError::Empty)));
+    }
+
+    #[test]
+    fn identity_cert_position() {
+        // the platform identity cert at the leaf is ignored, only the cert
+        // at `PLATFORM_ID_INDEX` is used
+        let pki_path = load(config::SIGNER_PKIPATH);
+        let reversed: Vec<_> = pki_path[..=PLATFORM_ID_INDEX]
+            .iter()
+            .rev()
+            .cloned()
+            .collect();
+        let result = PlatformIdentity::from_pki_path(&reversed);
+        assert!(matches!(
+            result,
+            Err(PlatformIdentityError::PlatformId(ref cn)) if cn == "alias"
+        ));
+    }
+
+    #[test]
+    fn parse_platform_id() {
+        assert!(
+            PlatformIdentity::parse("PDV1:913-0000019:6:BRM42220036", "a")
+                .is_some()
+        );
+        for platform_id in [
+            "test-device-id",
+            "XXV2:PPP-PPPPPPP:RRR:00000000001",
+            "PDV2:PPP-PPPPPPP:RRR",
+            "PDV2:PPP-PPPPPPP:RRR:",
+            "PDV2:PPP-PPPPPPP:RRR:00000000001:0",
+        ] {
+            assert!(PlatformIdentity::parse(platform_id, "a").is_none());
+        }
+    }
+}
@@ -0,0 +1,386 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{VmInstanceConf, platform_identity::PlatformIdentity};
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+/// Errors produced while loading an `InstancePolicy`.
+#[derive(Debug, thiserror::Error)]
+pub enum PolicyError {
+    #[error("failed to read policy file")]
+    Io(#[from] std::io::Error),
+    #[error("failed to parse KDL policy")]
+    Kdl(#[from] knuffel::Error),
+    #[error("rule for field \"{0}\" has no conditions")]
+    EmptyRule(String),
+}
+
+/// A single rule from a KDL policy document:
+/// ```kdl
+/// rule "image-digest.digest" {
+///     one-of "be4d..." "72fa..."
+/// }
+/// ```
+#[derive(knuffel::Decode, Debug)]
+struct RuleNode {
+    #[knuffel(argument)]
+    field: String,
+    #[knuffel(child)]
+    any: bool,
+    #[knuffel(child, unwrap(arguments))]
+    one_of: Option<Vec<String>>,
+    #[knuffel(child, unwrap(argument))]
+    at_least: Option<u64>,
+    #[knuffel(child, unwrap(argument))]
+    at_most: Option<u64>,
+}
+
+#[derive(knuffel::Decode, Debug)]
+enum PolicyNode {
+    Rule(RuleNode),
+}
+
+/// A condition that a single field from the `VmInstanceConf` must satisfy.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Condition {
+    /// The field must be present but may have any value.
+    Any,
+    /// The field must be equal to one of the listed values.
+    OneOf(Vec<String>),
+    /// The field must be an integer >= the provided value.
+    AtLeast(u64),
+    /// The field must be an integer <= the provided value.
+    AtMost(u64),
+}
+
+impl Condition {
+    fn evaluate(&self, value: &Value) -> bool {
+        match self {
+            Self::Any => true,
+            Self::OneOf(allowed) => match value_to_string(value) {
+                Some(value) => allowed.contains(&value),
+                None => false,
+            },
+            Self::AtLeast(min) => value.as_u64().is_some_and(|v| v >= *min),
+            Self::AtMost(max) => value.as_u64().is_some_and(|v| v <= *max),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Any => write!(f, "any"),
+            Self::OneOf(allowed) => {
+                write!(f, "one-of [{}]", allowed.join(", "))
+            }
+            Self::AtLeast(min) => write!(f, ">= {min}"),
+            Self::AtMost(max) => write!(f, "<= {max}"),
+        }
+    }
+}
+
+/// A `Condition` applied to the named field. Fields are named as they
+/// appear in the JSON encoding of `VmInstanceConf`, nested fields are
+/// separated by a '.' (e.g. `image-digest.digest`). The fields of the
+/// `PlatformIdentity` of the platform hosting the instance are under
+/// `platform` (e.g. `platform.serial`).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Rule {
+    pub field: String,
+    pub condition: Condition,
+}
+
+/// The outcome of evaluating a single `Rule`.
+#[derive(Clone, Debug, Serialize)]
+pub struct RuleResult {
+    pub rule: Rule,
+    /// The value of the field from the `VmInstanceConf`, or `None` if the
+    /// field isn't present.
+    pub actual: Option<String>,
+    pub passed: bool,
+}
+
+impl fmt::Display for RuleResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let actual = self.actual.as_deref().unwrap_or("<missing>");
+        let result = if self.passed { "pass" } else { "FAIL" };
+        write!(
+            f,
+            "{} {}: {} {}",
+            self.rule.field, self.rule.condition, actual, result
+        )
+    }
+}
+
+/// A collection of rules used to appraise the `VmInstanceConf` from the
+/// measurement log produced by the `RotType::OxideInstance`. Every rule must
+/// pass for the instance to be considered trustworthy.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct InstancePolicy {
+    pub rules: Vec<Rule>,
+}
+
+impl InstancePolicy {
+    /// Parse a policy from KDL. `name` is used in error messages.
+    pub fn from_kdl(name: &str, text: &str) -> Result<Self, PolicyError> {
+        let nodes: Vec<PolicyNode> = knuffel::parse(name, text)?;
+
+        let mut rules = Vec::new();
+        for node in nodes {
+            let PolicyNode::Rule(node) = node;
+            let count = rules.len();
+
+            let field = node.field;
+            let mut push = |condition| {
+                rules.push(Rule {
+                    field: field.clone(),
+                    condition,
+                })
+            };
+            if node.any {
+                push(Condition::Any);
+            }
+            if let Some(allowed) = node.one_of {
+                push(Condition::OneOf(allowed));
+            }
+            if let Some(min) = node.at_least {
+                push(Condition::AtLeast(min));
+            }
+            if let Some(max) = node.at_most {
+                push(Condition::AtMost(max));
+            }
+
+            if rules.len() == count {
+                return Err(PolicyError::EmptyRule(field));
+            }
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// Load a policy from a KDL file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PolicyError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+
+        Self::from_kdl(&path.display().to_string(), &text)
+    }
+
+    /// Evaluate each rule against the provided `VmInstanceConf` &
+    /// `PlatformIdentity`. Rules for `platform` fields fail if `platform`
+    /// is `None`.
+    pub fn evaluate(
+        &self,
+        conf: &VmInstanceConf,
+        platform: Option<&PlatformIdentity>,
+    ) -> Vec<RuleResult> {
+        // `VmInstanceConf` & `PlatformIdentity` are always representable as
+        // JSON
+        let mut conf = serde_json::to_value(conf)
+            .expect("VmInstanceConf serializes to JSON");
+        if let (Some(conf), Some(platform)) = (conf.as_object_mut(), platform) {
+            conf.insert(
+                "platform".to_string(),
+                serde_json::to_value(platform)
+                    .expect("PlatformIdentity serializes to JSON"),
+            );
+        }
+
+        self.rules
+            .iter()
+            .map(|rule| {
+                let value = lookup(&conf, &rule.field);
+                RuleResult {
+                    rule: rule.clone(),
+                    actual: value.and_then(value_to_string),
+                    passed: value.is_some_and(|v| rule.condition.evaluate(v)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The policy that requires an exact match with the provided reference.
+impl From<&VmInstanceConf> for InstancePolicy {
+    fn from(reference: &VmInstanceConf) -> Self {
+        let exact = |field: &str, value: String| Rule {
+            field: field.to_string(),
+            condition: Condition::OneOf(vec![value]),
+        };
+
+        Self {
+            rules: vec![
+                exact("uuid", reference.uuid.to_string()),
+                exact(
+                    "image-digest.algorithm",
+                    reference.image_digest.algorithm.clone(),
+                ),
+                exact(
+                    "image-digest.digest",
+                    reference.image_digest.digest.clone(),
+                ),
+            ],
+        }
+    }
+}
+
+/// Find the value of the '.' separated `field` in `value`.
+fn lookup<'a>(value: &'a Value, field: &str) -> Option<&'a Value> {
+    field
+        .split('.')
+        .try_fold(value, |value, name| value.get(name))
+}
+
+/// Every field of `conf` w/ a scalar value, named as in a `Rule`.
+pub(crate) fn fields(conf: &VmInstanceConf) -> BTreeMap<String, String> {
+    fn flatten(
+        field: &str,
+        value: &Value,
+        fields: &mut BTreeMap<String, String>,
+    ) {
+        match value {
+            Value::Object(map) => {
+                for (name, value) in map {
+                    let name = match field {
+                        "" => name.clone(),
+                        field => format!("{field}.{name}"),
+                    };
+                    flatten(&name, value, fields);
+                }
+            }
+            value => {
+                if let Some(value) = value_to_string(value) {
+                    fields.insert(field.to_string(), value);
+                }
+            }
+        }
+    }
+
+    // `VmInstanceConf` is always representable as JSON
+    let conf =
+        serde_json::to_value(conf).expect("VmInstanceConf serializes to JSON");
+    let mut fields = BTreeMap::new();
+    flatten("", &conf, &mut fields);
+
+    fields
+}
+
+fn value_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::config;
+
+    fn instance_cfg() -> VmInstanceConf {
+        let instance_cfg = fs::read_to_string(config::VM_INSTANCE_CFG)
+            .expect("read VM_INSTANCE_CFG to string");
+        serde_json::from_str(&instance_cfg)
+            .expect("parse JSON from mock cfg for instance RoT")
+    }
+
+    #[test]
+    fn policy_from_file() {
+        let policy = InstancePolicy::from_file(config::INSTANCE_POLICY)
+            .expect("InstancePolicy from file");
+
+        let results = policy.evaluate(&instance_cfg(), None);
+        assert_eq!(results.len(), policy.rules.len());
+        for result in results {
+            assert!(result.passed, "{result}");
+        }
+    }
+
+    #[test]
+    fn policy_from_reference() {
+        let instance_cfg = instance_cfg();
+        let policy = InstancePolicy::from(&instance_cfg);
+        assert!(
+            policy
+                .evaluate(&instance_cfg, None)
+                .iter()
+                .all(|r| r.passed)
+        );
+
+        let mut other = instance_cfg;
+        other.uuid = uuid::Uuid::nil();
+        let failed: Vec<_> = policy
+            .evaluate(&other, None)
+            .into_iter()
+            .filter(|r| !r.passed)
+            .collect();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].rule.field, "uuid");
+    }
+
+    #[test]
+    fn policy_missing_field() {
+        let policy = InstancePolicy::from_kdl(
+            "test",
+            r#"
+            rule "memory" {
+                at-least 8589934592
+            }
+            "#,
+        )
+        .expect("InstancePolicy from KDL");
+
+        let results = policy.evaluate(&instance_cfg(), None);
+        assert_eq!(results.len(), 1);
+        assert!(!results[0].passed);
+        assert_eq!(results[0].actual, None);
+    }
+
+    #[test]
+    fn policy_platform() {
+        let policy = InstancePolicy::from_kdl(
+            "test",
+            r#"
+            rule "platform.serial" {
+                one-of "00000000001"
+            }
+            "#,
+        )
+        .expect("InstancePolicy from KDL");
+        let platform = PlatformIdentity {
+            platform_id: "PDV2:PPP-PPPPPPP:RRR:00000000001".to_string(),
+            part: "PPP-PPPPPPP".to_string(),
+            revision: "RRR".to_string(),
+            serial: "00000000001".to_string(),
+            alias: "alias".to_string(),
+        };
+
+        let results = policy.evaluate(&instance_cfg(), Some(&platform));
+        assert!(results[0].passed, "{}", results[0]);
+
+        let other = PlatformIdentity {
+            serial: "00000000002".to_string(),
+            ..platform
+        };
+        let results = policy.evaluate(&instance_cfg(), Some(&other));
+        assert!(!results[0].passed);
+
+        // no platform identity to evaluate the rule against
+        let results = policy.evaluate(&instance_cfg(), None);
+        assert!(!results[0].passed);
+        assert_eq!(results[0].actual, None);
+    }
+
+    #[test]
+    fn policy_empty_rule() {
+        let result = InstancePolicy::from_kdl("test", r#"rule "uuid""#);
+        assert!(matches!(result, Err(PolicyError::EmptyRule(_))));
+    }
+}
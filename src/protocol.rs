@@ -0,0 +1,414 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A simple protocol for exposing an `AttestationSigner` over a stream
+//! socket. Each request & response is a single line of JSON. `serve` handles
+//! requests from a client w/ any `AttestationSigner` and `Client` is an
+//! `AttestationSigner` that forwards each call to an endpoint.
+
+use crate::{
+    Attestation, AttestationSigner, CertChain, ErrorKind, MeasurementLog,
+    Nonce, SignerError,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+    time::Duration,
+};
+
+/// The longest request or response line, in bytes, accepted from a peer.
+pub const MAX_LINE: u64 = 1024 * 1024;
+
+/// The read & write timeout for streams connected to a peer.
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A call to one of the `AttestationSigner` functions.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "request", rename_all = "kebab-case")]
+pub enum Request {
+    Attest {
+        nonce: Nonce,
+        #[serde(with = "crate::hex_bytes")]
+        user_data: Vec<u8>,
+    },
+    GetMeasurementLogs,
+    GetCertChains,
+}
+
+/// The result of a `Request`.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Response {
+    Attestations(Vec<Attestation>),
+    MeasurementLogs(Vec<MeasurementLog>),
+    CertChains(Vec<CertChain>),
+    Error(RemoteError),
+}
+
+/// An error returned by the `AttestationSigner` behind an endpoint.
+#[derive(Debug, Deserialize, Serialize, thiserror::Error)]
+#[serde(rename_all = "kebab-case")]
+#[error("{kind} error from endpoint: {message}")]
+pub struct RemoteError {
+    pub kind: ErrorKind,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
+}
+
+impl<E: SignerError> From<&E> for RemoteError {
+    fn from(e: &E) -> Self {
+        Self {
+            kind: e.kind(),
+            message: e.to_string(),
+            retry_after_ms: e
+                .retry_after()
+                .map(|d| d.as_millis().try_into().unwrap_or(u64::MAX)),
+        }
+    }
+}
+
+/// Errors produced by `serve` & `Client`.
+#[derive(Debug, thiserror::Error)]
+pub enum ProtocolError {
+    #[error("I/O error communicating w/ endpoint")]
+    Io(#[from] io::Error),
+    #[error("failed to encode / decode JSON message")]
+    Json(#[from] serde_json::Error),
+    #[error("endpoint closed the connection")]
+    Closed,
+    #[error("message longer than {MAX_LINE} bytes")]
+    LineTooLong,
+    #[error("endpoint returned a response for a different request")]
+    UnexpectedResponse,
+    #[error(transparent)]
+    Remote(#[from] RemoteError),
+    #[error("invalid endpoint \"{0}\", expected unix:PATH or tcp:ADDR")]
+    Endpoint(String),
+}
+
+impl SignerError for ProtocolError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            ProtocolError::Io(_) | ProtocolError::Closed => {
+                ErrorKind::Unavailable
+            }
+            ProtocolError::Json(_)
+            | ProtocolError::LineTooLong
+            | ProtocolError::UnexpectedResponse => ErrorKind::Internal,
+            ProtocolError::Remote(e) => e.kind,
+            ProtocolError::Endpoint(_) => ErrorKind::InvalidInput,
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ProtocolError::Remote(e) => {
+                e.retry_after_ms.map(Duration::from_millis)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The address of an endpoint: either the path to a unix domain socket or
+/// a TCP address.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Endpoint {
+    Unix(PathBuf),
+    Tcp(String),
+}
+
+impl FromStr for Endpoint {
+    type Err = ProtocolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("unix", path)) if !path.is_empty() => {
+                Ok(Endpoint::Unix(path.into()))
+            }
+            Some(("tcp", addr)) if !addr.is_empty() => {
+                Ok(Endpoint::Tcp(addr.to_string()))
+            }
+            _ => Err(ProtocolError::Endpoint(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+            Endpoint::Tcp(addr) => write!(f, "tcp:{addr}"),
+        }
+    }
+}
+
+/// Read a single line from `reader` into `line`, returning the number of
+/// bytes read or 0 at EOF. Lines longer than `MAX_LINE` are rejected.
+fn read_line<R: BufRead>(
+    reader: &mut R,
+    line: &mut String,
+) -> Result<usize, ProtocolError> {
+    line.clear();
+    let len = reader.take(MAX_LINE + 1).read_line(line)?;
+    if len as u64 > MAX_LINE {
+        return Err(ProtocolError::LineTooLong);
+    }
+
+    Ok(len)
+}
+
+/// Set `TIMEOUT` as the read & write timeout on `stream`.
+pub fn set_unix_timeouts(stream: &UnixStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))
+}
+
+/// Set `TIMEOUT` as the read & write timeout on `stream`.
+pub fn set_tcp_timeouts(stream: &TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))
+}
+
+/// Handle requests read from `reader` w/ `signer`, writing each response to
+/// `writer`, until the client closes the connection. Errors from the signer
+/// are returned to the client. Requests longer than `MAX_LINE` close the
+/// connection w/ `ProtocolError::LineTooLong`.
+pub fn serve<S, R, W>(
+    signer: &S,
+    mut reader: R,
+    mut writer: W,
+) -> Result<(), ProtocolError>
+where
+    S: AttestationSigner,
+    R: BufRead,
+    W: Write,
+{
+    let mut line = String::new();
+    while read_line(&mut reader, &mut line)? != 0 {
+        let response = match serde_json::from_str(&line) {
+            Ok(Request::Attest { nonce, user_data }) => signer
+                .attest(&nonce, &user_data)
+                .map(Response::Attestations),
+            Ok(Request::GetMeasurementLogs) => {
+                signer.get_measurement_logs().map(Response::MeasurementLogs)
+            }
+            Ok(Request::GetCertChains) => {
+                signer.get_cert_chains().map(Response::CertChains)
+            }
+            Err(e) => Ok(Response::Error(RemoteError {
+                kind: ErrorKind::InvalidInput,
+                message: format!("malformed request: {e}"),
+                retry_after_ms: None,
+            })),
+        }
+        .unwrap_or_else(|e| Response::Error((&e).into()));
+
+        serde_json::to_writer(&mut writer, &response)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+struct Connection {
+    reader: Box<dyn BufRead + Send>,
+    writer: Box<dyn Write + Send>,
+}
+
+/// An `AttestationSigner` that forwards each call to an endpoint serving
+/// requests w/ `serve`.
+pub struct Client {
+    connection: Mutex<Connection>,
+}
+
+impl Client {
+    pub fn new<R, W>(reader: R, writer: W) -> Self
+    where
+        R: BufRead + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        Self {
+            connection: Mutex::new(Connection {
+                reader: Box::new(reader),
+                writer: Box::new(writer),
+            }),
+        }
+    }
+
+    /// Connect to the endpoint at `endpoint`. Calls fail if the endpoint
+    /// doesn't respond within `TIMEOUT`.
+    pub fn connect(endpoint: &Endpoint) -> Result<Self, ProtocolError> {
+        Ok(match endpoint {
+            Endpoint::Unix(path) => {
+                let stream = UnixStream::connect(path)?;
+                set_unix_timeouts(&stream)?;
+                Self::new(BufReader::new(stream.try_clone()?), stream)
+            }
+            Endpoint::Tcp(addr) => {
+                let stream = TcpStream::connect(addr)?;
+                set_tcp_timeouts(&stream)?;
+                Self::new(BufReader::new(stream.try_clone()?), stream)
+            }
+        })
+    }
+
+    fn call(&self, request: &Request) -> Result<Response, ProtocolError> {
+        let mut connection =
+            self.connection.lock().expect("connection lock poisoned");
+
+        serde_json::to_writer(&mut connection.writer, request)?;
+        connection.writer.write_all(b"\n")?;
+        connection.writer.flush()?;
+
+        let mut line = String::new();
+        if read_line(&mut connection.reader, &mut line)? == 0 {
+            return Err(ProtocolError::Closed);
+        }
+
+        match serde_json::from_str(&line)? {
+            Response::Error(e) => Err(e.into()),
+            response => Ok(response),
+        }
+    }
+}
+
+impl AttestationSigner for Client {
+    type Error = ProtocolError;
+
+    fn attest(
+        &self,
+        nonce: &Nonce,
+        user_data: &[u8],
+    ) -> Result<Vec<Attestation>, Self::Error> {
+        let request = Request::Attest {
+            nonce: nonce.clone(),
+            user_data: user_data.to_vec(),
+        };
+        match self.call(&request)? {
+            Response::Attestations(attestations) => Ok(attestations),
+            _ => Err(ProtocolError::UnexpectedResponse),
+        }
+    }
+
+    fn get_measurement_logs(&self) -> Result<Vec<MeasurementLog>, Self::Error> {
+        match self.call(&Request::GetMeasurementLogs)? {
+            Response::MeasurementLogs(logs) => Ok(logs),
+            _ => Err(ProtocolError::UnexpectedResponse),
+        }
+    }
+
+    fn get_cert_chains(&self) -> Result<Vec<CertChain>, Self::Error> {
+        match self.call(&Request::GetCertChains)? {
+            Response::CertChains(cert_chains) => Ok(cert_chains),
+            _ => Err(ProtocolError::UnexpectedResponse),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fault::{Call, Fault, FaultSigner, Trigger},
+        test::{setup, verifier_config},
+        verifier::{Evidence, Verifier},
+    };
+    use std::thread;
+
+    const USER_DATA: [u8; 32] = [0u8; 32];
+
+    /// Serve requests from a `Client` w/ `signer`, returning the result of
+    /// `client_fn` run against the client on another thread.
+    fn with_client<S, F, T>(signer: &S, client_fn: F) -> T
+    where
+        S: AttestationSigner,
+        F: FnOnce(Client) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (server, client) = UnixStream::pair().expect("socket pair");
+        let client = thread::spawn(move || {
+            let reader =
+                BufReader::new(client.try_clone().expect("clone client"));
+            client_fn(Client::new(reader, client))
+        });
+
+        let reader = BufReader::new(server.try_clone().expect("clone server"));
+        serve(signer, reader, server).expect("serve");
+
+        client.join().expect("client thread")
+    }
+
+    #[test]
+    fn endpoint_from_str() {
+        assert_eq!(
+            "unix:/run/vm-attest.sock".parse::<Endpoint>().ok(),
+            Some(Endpoint::Unix("/run/vm-attest.sock".into()))
+        );
+        assert_eq!(
+            "tcp:localhost:8080".parse::<Endpoint>().ok(),
+            Some(Endpoint::Tcp("localhost:8080".into()))
+        );
+        assert!("localhost:8080".parse::<Endpoint>().is_err());
+        assert!("unix:".parse::<Endpoint>().is_err());
+    }
+
+    #[test]
+    fn client_evidence() {
+        let verifier = Verifier::from_config(&verifier_config())
+            .expect("Verifier from config");
+        let nonce = verifier.issue_nonce().expect("issue nonce");
+
+        let client_nonce = nonce.clone();
+        let evidence = with_client(&setup(), move |client| {
+            Evidence::collect(&client, &client_nonce, &USER_DATA)
+                .expect("evidence from client")
+        });
+
+        let report = verifier
+            .verify(&evidence, &nonce, &USER_DATA)
+            .expect("verify evidence from client");
+        assert!(report.is_ok(), "{report}");
+    }
+
+    #[test]
+    fn line_too_long() {
+        let long = vec![b'a'; MAX_LINE as usize + 1];
+
+        let mut writer = Vec::new();
+        let result =
+            serve(&setup(), io::Cursor::new(long.clone()), &mut writer);
+        assert!(matches!(result, Err(ProtocolError::LineTooLong)));
+        assert!(writer.is_empty());
+
+        let client = Client::new(io::Cursor::new(long), io::sink());
+        let result = client.get_cert_chains();
+        assert!(matches!(result, Err(ProtocolError::LineTooLong)));
+    }
+
+    #[test]
+    fn client_remote_error() {
+        let signer = FaultSigner::new(setup()).with_fault(
+            Call::GetCertChains,
+            Trigger::Always,
+            Fault::Error(ErrorKind::Unavailable),
+        );
+
+        let result = with_client(&signer, |client| client.get_cert_chains());
+        match result {
+            Err(e @ ProtocolError::Remote(_)) => {
+                assert_eq!(e.kind(), ErrorKind::Unavailable)
+            }
+            _ => panic!("expected remote error"),
+        }
+    }
+}
@@ -0,0 +1,311 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{
+    Attestation, AttestationSigner, CertChain, ErrorKind, MeasurementLog,
+    Nonce, SignerError,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// A source of the current time. This allows tests to control the passage
+/// of time seen by the `RateLimiter`.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// A `Clock` backed by `Instant::now`.
+#[derive(Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A `Clock` that only moves when `advance` is called.
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().expect("clock lock poisoned") += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().expect("clock lock poisoned")
+    }
+}
+
+/// Errors returned by a `RateLimitedSigner`.
+#[derive(Debug, thiserror::Error)]
+pub enum RateLimitError<E> {
+    #[error("attestation rate limit exceeded, retry after {retry_after:?}")]
+    RateLimited { retry_after: Duration },
+    #[error("error from the wrapped AttestationSigner")]
+    Signer(#[source] E),
+}
+
+impl<E: SignerError + 'static> SignerError for RateLimitError<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            RateLimitError::RateLimited { .. } => ErrorKind::Unavailable,
+            RateLimitError::Signer(e) => e.kind(),
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            RateLimitError::RateLimited { retry_after } => Some(*retry_after),
+            RateLimitError::Signer(e) => e.retry_after(),
+        }
+    }
+}
+
+/// A token bucket holding up to `capacity` tokens, refilled at a rate of
+/// one token per `refill`.
+struct TokenBucket {
+    tokens: u32,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn take(
+        &mut self,
+        now: Instant,
+        capacity: u32,
+        refill: Duration,
+    ) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        let new = elapsed.as_nanos() / refill.as_nanos().max(1);
+        let new = u32::try_from(new).unwrap_or(u32::MAX);
+        if self.tokens.saturating_add(new) >= capacity {
+            self.tokens = capacity;
+            self.last_refill = now;
+        } else {
+            self.tokens += new;
+            self.last_refill += refill * new;
+        }
+
+        if self.tokens == 0 {
+            return Err(refill.saturating_sub(
+                now.saturating_duration_since(self.last_refill),
+            ));
+        }
+        self.tokens -= 1;
+
+        Ok(())
+    }
+}
+
+/// Per-instance token buckets limiting how often each VM on the sled may
+/// request an attestation from the shared platform RoT. Each instance may
+/// make a burst of up to `capacity` requests, after which it's limited to
+/// one request per `refill`. The limiter is shared by the
+/// `RateLimitedSigner` for each instance.
+pub struct RateLimiter {
+    capacity: u32,
+    refill: Duration,
+    clock: Arc<dyn Clock>,
+    buckets: Mutex<HashMap<Uuid, TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(capacity: u32, refill: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            capacity,
+            refill,
+            clock,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a token from the bucket for `instance`. If the bucket is empty
+    /// the time until the next token is available is returned as the error.
+    pub fn acquire(&self, instance: &Uuid) -> Result<(), Duration> {
+        let now = self.clock.now();
+        self.buckets
+            .lock()
+            .expect("rate limiter lock poisoned")
+            .entry(*instance)
+            .or_insert(TokenBucket {
+                tokens: self.capacity,
+                last_refill: now,
+            })
+            .take(now, self.capacity, self.refill)
+    }
+
+    /// Forget the bucket for an instance that has been destroyed.
+    pub fn remove(&self, instance: &Uuid) {
+        self.buckets
+            .lock()
+            .expect("rate limiter lock poisoned")
+            .remove(instance);
+    }
+}
+
+/// Wraps the `AttestationSigner` for a single VM instance limiting calls to
+/// `attest` w/ the shared `RateLimiter`. Measurement logs & cert chains
+/// don't require a signature from the RoT and aren't limited.
+pub struct RateLimitedSigner<S> {
+    inner: S,
+    instance: Uuid,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S> RateLimitedSigner<S> {
+    pub fn new(inner: S, instance: Uuid, limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            inner,
+            instance,
+            limiter,
+        }
+    }
+}
+
+impl<S: AttestationSigner> AttestationSigner for RateLimitedSigner<S> {
+    type Error = RateLimitError<S::Error>;
+
+    fn attest(
+        &self,
+        nonce: &Nonce,
+        user_data: &[u8],
+    ) -> Result<Vec<Attestation>, Self::Error> {
+        self.limiter
+            .acquire(&self.instance)
+            .map_err(|retry_after| RateLimitError::RateLimited {
+                retry_after,
+            })?;
+
+        self.inner
+            .attest(nonce, user_data)
+            .map_err(RateLimitError::Signer)
+    }
+
+    fn get_measurement_logs(&self) -> Result<Vec<MeasurementLog>, Self::Error> {
+        self.inner
+            .get_measurement_logs()
+            .map_err(RateLimitError::Signer)
+    }
+
+    fn get_cert_chains(&self) -> Result<Vec<CertChain>, Self::Error> {
+        self.inner.get_cert_chains().map_err(RateLimitError::Signer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+
+    const NONCE: [u8; 32] = [0u8; 32];
+    const USER_DATA: [u8; 32] = [0u8; 32];
+
+    fn assert_limited<E: SignerError + 'static>(
+        result: Result<Vec<Attestation>, RateLimitError<E>>,
+        expected: Duration,
+    ) {
+        match result {
+            Err(e @ RateLimitError::RateLimited { .. }) => {
+                assert_eq!(e.kind(), ErrorKind::Unavailable);
+                assert_eq!(e.retry_after(), Some(expected));
+            }
+            Err(RateLimitError::Signer(_)) => panic!("unexpected signer error"),
+            Ok(_) => panic!("attest wasn't rate limited"),
+        }
+    }
+
+    #[test]
+    fn rate_limited() {
+        let clock = Arc::new(ManualClock::new());
+        let limiter = Arc::new(RateLimiter::new(
+            2,
+            Duration::from_secs(1),
+            clock.clone(),
+        ));
+        let nonce = Nonce::from_array(NONCE);
+
+        let attest = setup();
+        let instance = attest.log.uuid;
+        let signer = RateLimitedSigner::new(attest, instance, limiter.clone());
+
+        // burst of 2 then limited
+        for _ in 0..2 {
+            signer
+                .attest(&nonce, &USER_DATA)
+                .expect("attest within limit");
+        }
+        assert_limited(
+            signer.attest(&nonce, &USER_DATA),
+            Duration::from_secs(1),
+        );
+
+        // other calls aren't limited
+        signer.get_measurement_logs().expect("get_measurement_logs");
+        signer.get_cert_chains().expect("get_cert_chains");
+
+        clock.advance(Duration::from_millis(400));
+        assert_limited(
+            signer.attest(&nonce, &USER_DATA),
+            Duration::from_millis(600),
+        );
+
+        clock.advance(Duration::from_millis(600));
+        signer
+            .attest(&nonce, &USER_DATA)
+            .expect("attest after refill");
+        assert_limited(
+            signer.attest(&nonce, &USER_DATA),
+            Duration::from_secs(1),
+        );
+
+        // each instance has its own bucket
+        let other = RateLimitedSigner::new(setup(), Uuid::nil(), limiter);
+        other
+            .attest(&nonce, &USER_DATA)
+            .expect("attest for other instance");
+    }
+
+    #[test]
+    fn bucket_refills_to_capacity() {
+        let clock = Arc::new(ManualClock::new());
+        let limiter =
+            RateLimiter::new(3, Duration::from_secs(1), clock.clone());
+        let instance = Uuid::nil();
+
+        for _ in 0..3 {
+            limiter.acquire(&instance).expect("acquire within limit");
+        }
+        assert!(limiter.acquire(&instance).is_err());
+
+        // an idle instance doesn't accumulate more than `capacity` tokens
+        clock.advance(Duration::from_secs(60));
+        for _ in 0..3 {
+            limiter.acquire(&instance).expect("acquire after refill");
+        }
+        assert_eq!(limiter.acquire(&instance), Err(Duration::from_secs(1)));
+    }
+}
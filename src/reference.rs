@@ -0,0 +1,378 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Generate reference values from a raw disk image: the `image-digest`
+//! expected in the `VmInstanceConf` for VMs booted from the image, a CoRIM
+//! holding the same digest, and an `InstancePolicy` requiring it.
+
+use crate::Measurement;
+use serde::Serialize;
+use sha2::digest::DynDigest;
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// The size of the buffer used to read the image.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Zeros hashed in place of the holes in a sparse image.
+static ZEROS: [u8; 64 * 1024] = [0u8; 64 * 1024];
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReferenceError {
+    #[error("failed to read disk image: {0}")]
+    Io(PathBuf, #[source] io::Error),
+    #[error("unsupported digest algorithm: {0}")]
+    Algorithm(String),
+}
+
+/// The digest algorithms supported for the `image-digest`. Each is named
+/// as in `Measurement::algorithm`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DigestAlgorithm {
+    #[default]
+    Sha256,
+    Sha384,
+    Sha512,
+    Sha3_256,
+    Sha3_384,
+    Sha3_512,
+}
+
+impl DigestAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha-256",
+            Self::Sha384 => "sha-384",
+            Self::Sha512 => "sha-512",
+            Self::Sha3_256 => "sha3-256",
+            Self::Sha3_384 => "sha3-384",
+            Self::Sha3_512 => "sha3-512",
+        }
+    }
+
+    /// The identifier for this algorithm from the IANA "Named Information
+    /// Hash Algorithm Registry" used by CoRIM.
+    pub fn corim_id(&self) -> i64 {
+        match self {
+            Self::Sha256 => 1,
+            Self::Sha384 => 7,
+            Self::Sha512 => 8,
+            Self::Sha3_256 => 10,
+            Self::Sha3_384 => 11,
+            Self::Sha3_512 => 12,
+        }
+    }
+
+    fn hasher(&self) -> Box<dyn DynDigest> {
+        match self {
+            Self::Sha256 => Box::new(sha2::Sha256::default()),
+            Self::Sha384 => Box::new(sha2::Sha384::default()),
+            Self::Sha512 => Box::new(sha2::Sha512::default()),
+            Self::Sha3_256 => Box::new(sha3::Sha3_256::default()),
+            Self::Sha3_384 => Box::new(sha3::Sha3_384::default()),
+            Self::Sha3_512 => Box::new(sha3::Sha3_512::default()),
+        }
+    }
+}
+
+impl fmt::Display for DigestAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for DigestAlgorithm {
+    type Err = ReferenceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            Self::Sha256,
+            Self::Sha384,
+            Self::Sha512,
+            Self::Sha3_256,
+            Self::Sha3_384,
+            Self::Sha3_512,
+        ]
+        .into_iter()
+        .find(|a| a.name() == s)
+        .ok_or_else(|| ReferenceError::Algorithm(s.to_string()))
+    }
+}
+
+/// The ranges of `file` holding data. Everything else is a hole that reads
+/// as zeros. If the filesystem can't report holes the whole file is data.
+fn data_extents(file: &File, len: u64) -> Vec<Range<u64>> {
+    let fd = file.as_raw_fd();
+    let seek = |offset: u64, whence| {
+        let offset = libc::off_t::try_from(offset).ok()?;
+        // SAFETY: lseek on a valid fd has no memory safety requirements
+        let result = unsafe { libc::lseek(fd, offset, whence) };
+        u64::try_from(result).ok()
+    };
+
+    let mut extents = Vec::new();
+    let mut offset = 0;
+    while offset < len {
+        let Some(start) = seek(offset, libc::SEEK_DATA) else {
+            // ENXIO: no data past `offset`, anything else: no hole support
+            if io::Error::last_os_error().raw_os_error() != Some(libc::ENXIO) {
+                extents.push(offset..len);
+            }
+            break;
+        };
+        let end = seek(start, libc::SEEK_HOLE).unwrap_or(len).min(len);
+        extents.push(start..end);
+        offset = end;
+    }
+
+    extents
+}
+
+fn hash_zeros(hasher: &mut dyn DynDigest, mut len: u64) {
+    while len > 0 {
+        let n = len.min(ZEROS.len() as u64) as usize;
+        hasher.update(&ZEROS[..n]);
+        len -= n as u64;
+    }
+}
+
+/// Stream the raw disk image at `path` through `algorithm`. Holes in a
+/// sparse image are hashed as zeros w/o reading them from disk.
+pub fn image_digest<P: AsRef<Path>>(
+    path: P,
+    algorithm: DigestAlgorithm,
+) -> Result<Measurement, ReferenceError> {
+    let path = path.as_ref();
+    let io_err = |e| ReferenceError::Io(path.to_path_buf(), e);
+
+    let mut file = File::open(path).map_err(io_err)?;
+    let len = file.metadata().map_err(io_err)?.len();
+
+    let mut hasher = algorithm.hasher();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut offset = 0;
+    for extent in data_extents(&file, len) {
+        hash_zeros(hasher.as_mut(), extent.start - offset);
+
+        file.seek(SeekFrom::Start(extent.start)).map_err(io_err)?;
+        let mut remaining = extent.end - extent.start;
+        while remaining > 0 {
+            let n = remaining.min(buf.len() as u64) as usize;
+            file.read_exact(&mut buf[..n]).map_err(io_err)?;
+            hasher.update(&buf[..n]);
+            remaining -= n as u64;
+        }
+        offset = extent.end;
+    }
+    hash_zeros(hasher.as_mut(), len - offset);
+
+    Ok(Measurement {
+        algorithm: algorithm.name().to_string(),
+        digest: hex::encode(hasher.finalize()),
+    })
+}
+
+/// The reference values for a disk image. The JSON encoding is the subset
+/// of the `VmInstanceConf` that's determined by the image.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReferenceValues {
+    pub image_digest: Measurement,
+    #[serde(skip)]
+    algorithm: DigestAlgorithm,
+}
+
+impl ReferenceValues {
+    /// Compute the reference values for the disk image at `path`.
+    pub fn from_image<P: AsRef<Path>>(
+        path: P,
+        algorithm: DigestAlgorithm,
+    ) -> Result<Self, ReferenceError> {
+        Ok(Self {
+            image_digest: image_digest(path, algorithm)?,
+            algorithm,
+        })
+    }
+
+    /// A CoRIM holding the image digest, as KDL in the format consumed by
+    /// the `attest-mock` tool (see test-data/corim.kdl).
+    pub fn to_corim_kdl(&self, vendor: &str, tag_id: &str) -> String {
+        format!(
+            r#"vendor {}
+tag-id {}
+id {}
+
+measurement {{
+    mkey "image-digest"
+    algorithm {}
+    digest {}
+}}
+"#,
+            kdl_string(vendor),
+            kdl_string(tag_id),
+            kdl_string(tag_id),
+            self.algorithm.corim_id(),
+            kdl_string(&self.image_digest.digest),
+        )
+    }
+
+    /// An `InstancePolicy` requiring the image digest, as KDL in the format
+    /// loaded by `InstancePolicy::from_kdl` (see
+    /// test-data/instance-policy.kdl).
+    pub fn to_policy_kdl(&self) -> String {
+        format!(
+            r#"rule "uuid" {{
+    any
+}}
+
+rule "image-digest.algorithm" {{
+    one-of {}
+}}
+
+rule "image-digest.digest" {{
+    one-of {}
+}}
+"#,
+            kdl_string(&self.image_digest.algorithm),
+            kdl_string(&self.image_digest.digest),
+        )
+    }
+}
+
+/// Quote & escape `s` as a KDL string.
+fn kdl_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '\u{8}' => quoted.push_str("\\b"),
+            '\u{c}' => quoted.push_str("\\f"),
+            c if c.is_control() => {
+                quoted.push_str(&format!("\\u{{{:x}}}", u32::from(c)))
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{VmInstanceConf, policy::InstancePolicy};
+    use sha2::{Digest, Sha256};
+    use std::{env, fs, io::Write, process};
+
+    /// A sparse file of `len` bytes w/ `data` written at each offset.
+    fn sparse_image(name: &str, len: u64, data: &[(u64, &[u8])]) -> PathBuf {
+        let path =
+            env::temp_dir().join(format!("{name}-{}.img", process::id()));
+        let mut file = File::create(&path).expect("create image");
+        file.set_len(len).expect("set image length");
+        for (offset, data) in data {
+            file.seek(SeekFrom::Start(*offset)).expect("seek image");
+            file.write_all(data).expect("write image");
+        }
+
+        path
+    }
+
+    #[test]
+    fn sparse_image_digest() {
+        let len = 8 * CHUNK_SIZE as u64 + 17;
+        let data: [(u64, &[u8]); 3] = [
+            (0, b"boot sector"),
+            (3 * CHUNK_SIZE as u64 - 3, b"spans a chunk boundary"),
+            (len - 4, b"end!"),
+        ];
+        let path = sparse_image("sparse_image_digest", len, &data);
+
+        let mut expected = vec![0u8; len as usize];
+        for (offset, data) in data {
+            let offset = offset as usize;
+            expected[offset..offset + data.len()].copy_from_slice(data);
+        }
+        let expected = hex::encode(Sha256::digest(&expected));
+
+        let digest = image_digest(&path, DigestAlgorithm::Sha256);
+        fs::remove_file(&path).expect("remove image");
+
+        let digest = digest.expect("image digest");
+        assert_eq!(digest.algorithm, "sha-256");
+        assert_eq!(digest.digest, expected);
+    }
+
+    #[test]
+    fn empty_image_digest() {
+        let path = sparse_image("empty_image_digest", 0, &[]);
+        let digest = image_digest(&path, DigestAlgorithm::Sha3_256);
+        fs::remove_file(&path).expect("remove image");
+
+        assert_eq!(
+            digest.expect("image digest").digest,
+            "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a"
+        );
+    }
+
+    #[test]
+    fn kdl_escape() {
+        assert_eq!(kdl_string("sha-256"), r#""sha-256""#);
+        assert_eq!(kdl_string("a\"b\\c\nd"), r#""a\"b\\c\nd""#);
+        assert_eq!(kdl_string("\u{7f}"), r#""\u{7f}""#);
+    }
+
+    #[test]
+    fn reference_values() {
+        let path = sparse_image("reference_values", 4096, &[(0, b"image")]);
+        let values =
+            ReferenceValues::from_image(&path, DigestAlgorithm::Sha3_256);
+        fs::remove_file(&path).expect("remove image");
+        let values = values.expect("reference values");
+
+        let json = serde_json::to_value(&values).expect("encode JSON");
+        assert_eq!(json["image-digest"]["algorithm"], "sha3-256");
+        assert_eq!(json["image-digest"]["digest"], values.image_digest.digest);
+
+        // the policy passes the instance config w/ the image digest
+        let policy =
+            InstancePolicy::from_kdl("policy.kdl", &values.to_policy_kdl())
+                .expect("parse policy KDL");
+        let conf: VmInstanceConf = serde_json::from_value(serde_json::json!({
+            "uuid": "db5bf54c-48c5-4455-a1e1-6c7dfc26e351",
+            "image-digest": values.image_digest,
+        }))
+        .expect("VmInstanceConf from JSON");
+        let results = policy.evaluate(&conf, None);
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|r| r.passed));
+
+        let kdl = values.to_corim_kdl(r#"test "vendor""#, "image");
+        assert!(kdl.contains(r#"vendor "test \"vendor\"""#));
+        assert!(kdl.contains(r#"tag-id "image""#));
+        assert!(kdl.contains("algorithm 10\n"));
+        assert!(
+            kdl.contains(&format!("digest {:?}", values.image_digest.digest))
+        );
+
+        assert_eq!(
+            "sha3-256".parse::<DigestAlgorithm>().ok(),
+            Some(DigestAlgorithm::Sha3_256)
+        );
+        assert!("md5".parse::<DigestAlgorithm>().is_err());
+    }
+}
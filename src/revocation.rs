@@ -0,0 +1,324 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Certificate revocation checking w/ CRLs. Each CA in the platform PKI may
+//! publish a CRL listing the serial numbers of the certs it has revoked,
+//! e.g. the alias cert for a compromised sled RoT. A CRL is only trusted if
+//! it's signed by the issuer of the cert being checked: the next cert in
+//! the chain or a trust anchor. When a CA has published more than one CRL
+//! only the latest is used, and it must not be past its next update.
+
+use const_oid::{
+    ObjectIdentifier,
+    db::{rfc5912::ECDSA_WITH_SHA_384, rfc8410::ID_ED_25519},
+};
+use der::{Decode, Encode};
+use ed25519_dalek::{Signature, VerifyingKey};
+use p384::ecdsa::{DerSignature, signature::Verifier};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use x509_cert::{Certificate, crl::CertificateList, time::Time};
+
+const PEM_LABEL: &str = "X509 CRL";
+
+#[derive(Debug, thiserror::Error)]
+pub enum RevocationError {
+    #[error("failed to read CRL: {0}")]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("failed to decode CRL")]
+    Der(#[from] der::Error),
+    #[error("unexpected PEM label for CRL: {0}")]
+    PemLabel(String),
+    #[error("unsupported CRL signature algorithm: {0}")]
+    Algorithm(ObjectIdentifier),
+    #[error("malformed public key in CRL issuer cert")]
+    Key,
+    #[error("invalid CRL signature")]
+    Signature(#[from] ed25519_dalek::SignatureError),
+    #[error("no issuer for cert {0} to verify the CRL against")]
+    Issuer(usize),
+    #[error("cert {index} w/ serial number {serial_number} is revoked")]
+    Revoked { index: usize, serial_number: String },
+    #[error("CRL from the issuer of cert {index} expired at {next_update}")]
+    Expired { index: usize, next_update: Time },
+}
+
+/// The CRLs published by the CAs in the platform PKI.
+#[derive(Clone, Debug, Default)]
+pub struct RevocationList {
+    crls: Vec<CertificateList>,
+}
+
+impl RevocationList {
+    pub fn new(crls: Vec<CertificateList>) -> Self {
+        Self { crls }
+    }
+
+    /// Load a DER or PEM encoded CRL from each of `paths`.
+    pub fn from_files<P: AsRef<Path>>(
+        paths: &[P],
+    ) -> Result<Self, RevocationError> {
+        let crls = paths
+            .iter()
+            .map(|path| {
+                let path = path.as_ref();
+                let crl = fs::read(path)
+                    .map_err(|e| RevocationError::Io(path.to_path_buf(), e))?;
+                decode_crl(&crl)
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { crls })
+    }
+
+    /// Returns `true` if there are no CRLs.
+    pub fn is_empty(&self) -> bool {
+        self.crls.is_empty()
+    }
+
+    /// Check that no cert in `pki_path` has been revoked by its issuer.
+    /// `trust_anchors` holds the issuer of the last cert in the chain. Only
+    /// the latest CRL from each issuer, by `this_update`, is used & it must
+    /// not be past its `next_update` at `time`. The cert chain should
+    /// already be verified.
+    pub fn check(
+        &self,
+        pki_path: &[Certificate],
+        trust_anchors: &[Certificate],
+        time: SystemTime,
+    ) -> Result<(), RevocationError> {
+        for (index, cert) in pki_path.iter().enumerate() {
+            let tbs = &cert.tbs_certificate;
+            let crls: Vec<_> = self
+                .crls
+                .iter()
+                .filter(|crl| crl.tbs_cert_list.issuer == tbs.issuer)
+                .collect();
+            let Some(latest) = crls.iter().max_by_key(|crl| {
+                crl.tbs_cert_list.this_update.to_system_time()
+            }) else {
+                continue;
+            };
+
+            let issuer = pki_path
+                .get(index + 1)
+                .into_iter()
+                .chain(trust_anchors)
+                .find(|issuer| issuer.tbs_certificate.subject == tbs.issuer)
+                .ok_or(RevocationError::Issuer(index))?;
+            for crl in &crls {
+                verify_signature(crl, issuer)?;
+            }
+
+            let latest = &latest.tbs_cert_list;
+            if let Some(next_update) = latest
+                .next_update
+                .filter(|next_update| time > next_update.to_system_time())
+            {
+                return Err(RevocationError::Expired { index, next_update });
+            }
+            if latest
+                .revoked_certificates
+                .iter()
+                .flatten()
+                .any(|revoked| revoked.serial_number == tbs.serial_number)
+            {
+                return Err(RevocationError::Revoked {
+                    index,
+                    serial_number: tbs.serial_number.to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Decode a CRL that's either PEM or DER encoded.
+fn decode_crl(crl: &[u8]) -> Result<CertificateList, RevocationError> {
+    if !crl.starts_with(b"-----BEGIN") {
+        return Ok(CertificateList::from_der(crl)?);
+    }
+
+    let (label, der) = der::pem::decode_vec(crl).map_err(der::Error::from)?;
+    if label != PEM_LABEL {
+        return Err(RevocationError::PemLabel(label.to_string()));
+    }
+
+    Ok(CertificateList::from_der(&der)?)
+}
+
+/// Verify that `crl` was signed by `issuer`. The platform PKI uses P-384
+/// keys for the root & intermediate CAs and ed25519 keys for the rest.
+fn verify_signature(
+    crl: &CertificateList,
+    issuer: &Certificate,
+) -> Result<(), RevocationError> {
+    let tbs = crl.tbs_cert_list.to_der()?;
+    let signature = crl
+        .signature
+        .as_bytes()
+        .ok_or(RevocationError::Der(der::Tag::BitString.value_error()))?;
+    let key = issuer
+        .tbs_certificate
+        .subject_public_key_info
+        .subject_public_key
+        .as_bytes()
+        .ok_or(RevocationError::Key)?;
+
+    match crl.signature_algorithm.oid {
+        ID_ED_25519 => {
+            let key: [u8; 32] =
+                key.try_into().map_err(|_| RevocationError::Key)?;
+            let signature = Signature::from_slice(signature)?;
+            VerifyingKey::from_bytes(&key)?.verify_strict(&tbs, &signature)?;
+        }
+        ECDSA_WITH_SHA_384 => {
+            let key = p384::ecdsa::VerifyingKey::from_sec1_bytes(key)
+                .map_err(|_| RevocationError::Key)?;
+            key.verify(&tbs, &DerSignature::try_from(signature)?)?;
+        }
+        oid => return Err(RevocationError::Algorithm(oid)),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::config;
+
+    fn load(path: &str) -> Vec<Certificate> {
+        let pem = fs::read(path).expect("read cert chain");
+        Certificate::load_pem_chain(&pem).expect("parse cert chain")
+    }
+
+    /// The start of `year`.
+    fn start_of(year: u16) -> SystemTime {
+        der::DateTime::new(year, 1, 1, 0, 0, 0)
+            .expect("DateTime")
+            .to_system_time()
+    }
+
+    #[test]
+    fn revoked_alias() {
+        let crls =
+            RevocationList::from_files(&[config::CRL]).expect("load CRL");
+        let roots = load(config::PKI_ROOT);
+        let now = SystemTime::now();
+
+        // the alias cert for the platform RoT is revoked by its issuer
+        let result = crls.check(&load(config::SIGNER_PKIPATH), &roots, now);
+        assert!(matches!(
+            result,
+            Err(RevocationError::Revoked { index: 0, .. })
+        ));
+
+        // certs from the same issuer w/ other serial numbers aren't
+        crls.check(&load(config::EXPIRED_PKIPATH), &roots, now)
+            .expect("check expired alias chain");
+        crls.check(&load(config::SP_SIGNER_PKIPATH), &roots, now)
+            .expect("check SP alias chain");
+    }
+
+    #[test]
+    fn crl_expired() {
+        let roots = load(config::PKI_ROOT);
+        let pki_path = load(config::EXPIRED_PKIPATH);
+
+        // the CRL is current until 2100
+        let crls =
+            RevocationList::from_files(&[config::CRL]).expect("load CRL");
+        crls.check(&pki_path, &roots, start_of(2099))
+            .expect("check w/ current CRL");
+        let result = crls.check(&pki_path, &roots, start_of(2101));
+        assert!(matches!(
+            result,
+            Err(RevocationError::Expired { index: 0, .. })
+        ));
+
+        // the old CRL was only current until 2000
+        let crls = RevocationList::from_files(&[config::OLD_CRL])
+            .expect("load old CRL");
+        let result = crls.check(&pki_path, &roots, SystemTime::now());
+        assert!(matches!(
+            result,
+            Err(RevocationError::Expired { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn crl_latest() {
+        let roots = load(config::PKI_ROOT);
+
+        // the latest CRL from the device id is used regardless of order, so
+        // the alias cert is revoked & the old CRL being past its next update
+        // doesn't matter
+        for paths in [
+            [config::OLD_CRL, config::CRL],
+            [config::CRL, config::OLD_CRL],
+        ] {
+            let crls = RevocationList::from_files(&paths).expect("load CRLs");
+            let result = crls.check(
+                &load(config::SIGNER_PKIPATH),
+                &roots,
+                SystemTime::now(),
+            );
+            assert!(matches!(
+                result,
+                Err(RevocationError::Revoked { index: 0, .. })
+            ));
+        }
+
+        // before 2000 the old CRL was the latest & revoked nothing
+        let crls = RevocationList::from_files(&[config::OLD_CRL])
+            .expect("load old CRL");
+        crls.check(&load(config::SIGNER_PKIPATH), &roots, start_of(1999))
+            .expect("check w/ old CRL");
+    }
+
+    #[test]
+    fn revoked_platform_id() {
+        // the P-384 intermediate revokes the platform identity cert, cert 2
+        // in the chain
+        let crls = RevocationList::from_files(&[config::INTERMEDIATE_CRL])
+            .expect("load intermediate CRL");
+        let result = crls.check(
+            &load(config::SIGNER_PKIPATH),
+            &load(config::PKI_ROOT),
+            SystemTime::now(),
+        );
+        assert!(matches!(
+            result,
+            Err(RevocationError::Revoked { index: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn crl_der() {
+        let pem = fs::read(config::CRL).expect("read CRL");
+        let crl = decode_crl(&pem).expect("decode PEM CRL");
+        let der = crl.to_der().expect("encode CRL");
+        assert_eq!(decode_crl(&der).expect("decode DER CRL"), crl);
+    }
+
+    #[test]
+    fn crl_bad_signature() {
+        let pem = fs::read(config::CRL).expect("read CRL");
+        let mut crl = decode_crl(&pem).expect("decode CRL");
+        // the CRL no longer revokes the alias cert
+        crl.tbs_cert_list.revoked_certificates = None;
+
+        let result = RevocationList::new(vec![crl]).check(
+            &load(config::SIGNER_PKIPATH),
+            &load(config::PKI_ROOT),
+            SystemTime::now(),
+        );
+        assert!(matches!(result, Err(RevocationError::Signature(_))));
+    }
+}
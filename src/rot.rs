@@ -0,0 +1,635 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{
+    Attestation, CertChain, DecodedLog, LogDecodeError, MeasurementLog,
+    RotType,
+    appraise::{
+        self, AppraisalError, AppraisalReport, MeasurementSource,
+        ReferenceManifest,
+    },
+    instance_key::{self, InstanceKeyError},
+    pki_policy,
+    platform_identity::PlatformIdentity,
+    policy::InstancePolicy,
+    revocation::RevocationList,
+    trust_store::TrustStore,
+    verifier::VerifyError,
+};
+use dice_verifier::{Attestation as OxAttestation, Log};
+use serde::Serialize;
+use std::{fmt, sync::Arc, time::SystemTime};
+use x509_cert::Certificate;
+
+/// The operations required to verify & appraise the evidence produced by a
+/// single type of RoT. Support for a new `RotType` is added by implementing
+/// this trait and registering the implementation w/ `RotVerifiers`.
+pub trait RotVerifier: Send + Sync {
+    /// The `RotType` handled by this verifier.
+    fn rot(&self) -> RotType;
+
+    /// Verify the cert chain for this RoT. `cert_chain` is `None` if the
+    /// evidence has no cert chain for this RoT. Returns `None` if this RoT
+    /// has no cert chain to verify. CRLs must be current at `time`.
+    fn verify_cert_chain(
+        &self,
+        cert_chain: Option<&CertChain>,
+        time: SystemTime,
+    ) -> Result<Option<VerifiedChain>, VerifyError>;
+
+    /// Verify the attestation from this RoT over `data`, the 32 bytes the
+    /// `AttestationSigner` passed to each hardware RoT. `attestation`,
+    /// `cert_chain` & `log` are `None` if the evidence has no attestation,
+    /// cert chain or measurement log from this RoT. The cert chain has
+    /// already been verified by `verify_cert_chain`.
+    fn verify_attestation(
+        &self,
+        attestation: Option<&Attestation>,
+        cert_chain: Option<&CertChain>,
+        log: Option<&MeasurementLog>,
+        data: &[u8; 32],
+    ) -> Result<(), VerifyError>;
+
+    /// Decode the measurement log produced by this RoT.
+    fn decode_log(
+        &self,
+        log: &MeasurementLog,
+    ) -> Result<DecodedLog, LogDecodeError> {
+        log.decode()
+    }
+
+    /// Appraise the decoded log adding the results to `report`.
+    /// `cert_chains` are the cert chains from the evidence, including this
+    /// RoT's cert chain if it has one.
+    fn appraise_log(
+        &self,
+        log: &DecodedLog,
+        cert_chains: &[CertChain],
+        report: &mut AppraisalReport,
+    ) -> Result<(), AppraisalError>;
+}
+
+/// A cert chain that has been verified, the trust anchor that validated it
+/// & the identity of the platform that produced it.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct VerifiedChain {
+    pub rot: RotType,
+    /// The name of the anchor in the `TrustStore`.
+    pub trust_anchor: String,
+    /// `None` if the chain has no platform identity cert w/ a recognised
+    /// platform ID, in which case instance policy rules for `platform`
+    /// fields fail.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<PlatformIdentity>,
+}
+
+impl fmt::Display for VerifiedChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} cert chain: trust anchor {}, ",
+            self.rot, self.trust_anchor
+        )?;
+        match &self.platform {
+            Some(platform) => write!(f, "{platform}"),
+            None => write!(f, "unknown platform"),
+        }
+    }
+}
+
+/// A collection of `RotVerifier`s, at most one per `RotType`.
+#[derive(Default)]
+pub struct RotVerifiers {
+    verifiers: Vec<Box<dyn RotVerifier>>,
+}
+
+impl RotVerifiers {
+    /// Register a `RotVerifier`, replacing any previously registered
+    /// verifier for the same `RotType`.
+    pub fn register(&mut self, verifier: Box<dyn RotVerifier>) {
+        self.verifiers.retain(|v| v.rot() != verifier.rot());
+        self.verifiers.push(verifier);
+    }
+
+    /// Get the verifier registered for `rot`.
+    pub fn get(&self, rot: RotType) -> Option<&dyn RotVerifier> {
+        self.verifiers
+            .iter()
+            .find(|v| v.rot() == rot)
+            .map(|v| v.as_ref())
+    }
+
+    /// Verify the cert chain for each registered RoT w/ the CRLs current at
+    /// `time`. Cert chains from RoTs w/o a registered verifier are rejected,
+    /// as is more than one cert chain for the same RoT.
+    pub fn verify_cert_chains(
+        &self,
+        cert_chains: &[CertChain],
+        time: SystemTime,
+    ) -> Result<Vec<VerifiedChain>, VerifyError> {
+        if let Some(chain) =
+            cert_chains.iter().find(|c| self.get(c.rot).is_none())
+        {
+            return Err(VerifyError::UnsupportedRot(chain.rot));
+        }
+        if let Some(rot) = duplicate_rot(cert_chains.iter().map(|c| c.rot)) {
+            return Err(VerifyError::DuplicateCertChain(rot));
+        }
+
+        let mut verified = Vec::new();
+        for verifier in &self.verifiers {
+            verified.extend(verifier.verify_cert_chain(
+                find_cert_chain(cert_chains, verifier.rot()),
+                time,
+            )?);
+        }
+
+        Ok(verified)
+    }
+
+    /// Verify the attestation from each registered RoT. There may be at most
+    /// one attestation, cert chain & log per RoT, the attestations must be in
+    /// the relative order of the cert chains (RoTs w/o an attestation are
+    /// skipped), and attestations from RoTs w/o a registered verifier are
+    /// rejected. A RoT need not provide an attestation if its cert chain is
+    /// endorsed by another RoT that does: the leaf of its cert chain issued a
+    /// cert in the chain of the attesting RoT (e.g. an `InstanceKey`). That
+    /// cert must bind the measurement log of the endorsed RoT (see
+    /// `instance_key::PLATFORM_LOG_DIGEST`) so that the log is vouched for by
+    /// the endorsed RoT itself. If an attestation carries an
+    /// `InclusionProof` the RoT signed the root of a batch that includes
+    /// `data`, and the verifier is passed that root in place of `data`.
+    ///
+    /// NOTE: The endorsing cert is only trustworthy if the cert chains have
+    /// been verified w/ `verify_cert_chains`.
+    pub fn verify_attestations(
+        &self,
+        attestations: &[Attestation],
+        cert_chains: &[CertChain],
+        logs: &[MeasurementLog],
+        data: &[u8; 32],
+    ) -> Result<(), VerifyError> {
+        if let Some(rot) = duplicate_rot(cert_chains.iter().map(|c| c.rot)) {
+            return Err(VerifyError::DuplicateCertChain(rot));
+        }
+        if let Some(rot) = duplicate_rot(logs.iter().map(|l| l.rot)) {
+            return Err(VerifyError::DuplicateLog(rot));
+        }
+
+        for (index, attestation) in attestations.iter().enumerate() {
+            if self.get(attestation.rot).is_none() {
+                return Err(VerifyError::UnsupportedRot(attestation.rot));
+            }
+            if attestations[..index]
+                .iter()
+                .any(|a| a.rot == attestation.rot)
+            {
+                return Err(VerifyError::DuplicateAttestation(attestation.rot));
+            }
+        }
+
+        let mut last = None;
+        for attestation in attestations {
+            let position = cert_chains
+                .iter()
+                .position(|c| c.rot == attestation.rot)
+                .ok_or(VerifyError::NoCertChain(attestation.rot))?;
+            if last.is_some_and(|last| position <= last) {
+                return Err(VerifyError::AttestationOrder);
+            }
+            last = Some(position);
+        }
+
+        for verifier in &self.verifiers {
+            let rot = verifier.rot();
+            let attestation = attestations.iter().find(|a| a.rot == rot);
+            let cert_chain = find_cert_chain(cert_chains, rot);
+            let log = logs.iter().find(|l| l.rot == rot);
+
+            let endorsement = match attestation {
+                Some(_) => None,
+                None => cert_chain
+                    .and_then(|c| endorsing_cert(c, attestations, cert_chains)),
+            };
+            if let Some(cert) = endorsement {
+                let log = log.ok_or(VerifyError::NoMeasurementLog(rot))?;
+                instance_key::verify_platform_log(cert, log)?;
+                continue;
+            }
+
+            let signed = match attestation.and_then(|a| a.proof.as_ref()) {
+                Some(proof) => proof.root(data)?,
+                None => *data,
+            };
+
+            verifier.verify_attestation(
+                attestation,
+                cert_chain,
+                log,
+                &signed,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Decode & appraise each measurement log w/ the verifier registered
+    /// for the log's `RotType`. There must be a log from every registered
+    /// RoT.
+    ///
+    /// NOTE: This does not verify the cert chains or attestation. It should
+    /// only be used on logs & cert chains that have already been verified.
+    pub fn appraise(
+        &self,
+        cert_chains: &[CertChain],
+        logs: &[MeasurementLog],
+    ) -> Result<AppraisalReport, AppraisalError> {
+        let mut report = AppraisalReport::default();
+
+        for verifier in &self.verifiers {
+            if !logs.iter().any(|l| l.rot == verifier.rot()) {
+                return Err(AppraisalError::NoMeasurementLog(verifier.rot()));
+            }
+        }
+
+        for log in logs {
+            let verifier = self
+                .get(log.rot)
+                .ok_or(AppraisalError::UnsupportedRot(log.rot))?;
+            let decoded = verifier.decode_log(log)?;
+            verifier.appraise_log(&decoded, cert_chains, &mut report)?;
+        }
+
+        Ok(report)
+    }
+}
+
+/// If `cert_chain` is the tail of a longer cert chain from a RoT that
+/// provided an attestation, the cert in that chain issued by the leaf of
+/// `cert_chain`.
+fn endorsing_cert<'a>(
+    cert_chain: &CertChain,
+    attestations: &[Attestation],
+    cert_chains: &'a [CertChain],
+) -> Option<&'a Certificate> {
+    let tail = &cert_chain.pki_path;
+    attestations
+        .iter()
+        .filter_map(|a| find_cert_chain(cert_chains, a.rot))
+        .map(|c| &c.pki_path)
+        .find(|path| {
+            path.len() > tail.len()
+                && path[path.len() - tail.len()..] == tail[..]
+        })
+        .map(|path| &path[path.len() - tail.len() - 1])
+}
+
+/// The first `RotType` in `rots` that has already been seen.
+fn duplicate_rot(rots: impl Iterator<Item = RotType>) -> Option<RotType> {
+    let mut seen = Vec::new();
+    for rot in rots {
+        if seen.contains(&rot) {
+            return Some(rot);
+        }
+        seen.push(rot);
+    }
+
+    None
+}
+
+fn find_cert_chain(
+    cert_chains: &[CertChain],
+    rot: RotType,
+) -> Option<&CertChain> {
+    cert_chains.iter().find(|c| c.rot == rot)
+}
+
+/// The hubpacked log from a hardware RoT.
+fn hardware_log(log: &DecodedLog) -> Option<&Log> {
+    match log {
+        DecodedLog::OxideHardware(log) | DecodedLog::OxideSp(log) => Some(log),
+        DecodedLog::OxideInstance(_) => None,
+    }
+}
+
+/// `RotVerifier` for the hardware RoTs on the Oxide platform: the platform
+/// RoT (`RotType::OxideHardware`) and the SP RoT (`RotType::OxideSp`). Both
+/// produce a DICE cert chain, a hubpacked measurement log & an attestation
+/// signed by the leaf of the cert chain. The cert chain is verified against
+/// the `TrustStore` for the platform PKI, the constraints of the platform
+/// PKI (see `pki_policy`) & the CRLs.
+/// FWIDs from the cert chain and the entries from the measurement log are
+/// looked up in the reference manifests.
+pub struct OxideHardwareVerifier {
+    rot: RotType,
+    trust_store: Arc<TrustStore>,
+    manifests: Vec<ReferenceManifest>,
+    revocation: RevocationList,
+}
+
+impl OxideHardwareVerifier {
+    pub fn new(
+        rot: RotType,
+        trust_store: Arc<TrustStore>,
+        manifests: Vec<ReferenceManifest>,
+    ) -> Self {
+        Self {
+            rot,
+            trust_store,
+            manifests,
+            revocation: RevocationList::default(),
+        }
+    }
+
+    /// Reject cert chains w/ a cert revoked by one of the CRLs in
+    /// `revocation`.
+    pub fn with_revocation_list(mut self, revocation: RevocationList) -> Self {
+        self.revocation = revocation;
+        self
+    }
+}
+
+impl RotVerifier for OxideHardwareVerifier {
+    fn rot(&self) -> RotType {
+        self.rot
+    }
+
+    fn verify_cert_chain(
+        &self,
+        cert_chain: Option<&CertChain>,
+        time: SystemTime,
+    ) -> Result<Option<VerifiedChain>, VerifyError> {
+        let cert_chain =
+            cert_chain.ok_or(VerifyError::NoCertChain(self.rot()))?;
+        let anchor = self.trust_store.verify(&cert_chain.pki_path)?;
+        pki_policy::check_pki_path(&cert_chain.pki_path)
+            .map_err(|e| VerifyError::PkiPolicy(self.rot(), e))?;
+        self.revocation
+            .check(
+                &cert_chain.pki_path,
+                std::slice::from_ref(&anchor.cert),
+                time,
+            )
+            .map_err(|e| VerifyError::Revocation(self.rot(), e))?;
+        let platform =
+            PlatformIdentity::from_pki_path(&cert_chain.pki_path).ok();
+
+        Ok(Some(VerifiedChain {
+            rot: self.rot(),
+            trust_anchor: anchor.name,
+            platform,
+        }))
+    }
+
+    fn verify_attestation(
+        &self,
+        attestation: Option<&Attestation>,
+        cert_chain: Option<&CertChain>,
+        log: Option<&MeasurementLog>,
+        data: &[u8; 32],
+    ) -> Result<(), VerifyError> {
+        let attestation =
+            attestation.ok_or(VerifyError::NoAttestation(self.rot()))?;
+        // signer cert is the leaf
+        let signer = cert_chain
+            .and_then(|c| c.pki_path.first())
+            .ok_or(VerifyError::NoCertChain(self.rot()))?;
+        let log = log.ok_or(VerifyError::NoMeasurementLog(self.rot()))?;
+
+        let (attestation, _): (OxAttestation, _) =
+            hubpack::deserialize(&attestation.data)
+                .map_err(|_| VerifyError::Deserialize)?;
+        let log = self.decode_log(log)?;
+        let log = hardware_log(&log)
+            .ok_or(AppraisalError::UnexpectedLog(self.rot()))?;
+
+        dice_verifier::verify_attestation(
+            signer,
+            &attestation,
+            log,
+            &attest_data::Nonce { 0: *data },
+        )?;
+
+        Ok(())
+    }
+
+    fn appraise_log(
+        &self,
+        log: &DecodedLog,
+        cert_chains: &[CertChain],
+        report: &mut AppraisalReport,
+    ) -> Result<(), AppraisalError> {
+        let log = hardware_log(log)
+            .ok_or(AppraisalError::UnexpectedLog(self.rot()))?;
+        let cert_chain = find_cert_chain(cert_chains, self.rot())
+            .ok_or(AppraisalError::NoCertChain(self.rot()))?;
+
+        for (index, cert) in cert_chain.pki_path.iter().enumerate() {
+            for measurement in appraise::cert_fwids(cert)? {
+                let source = MeasurementSource::CertChain {
+                    rot: self.rot(),
+                    index,
+                };
+                report.measurements.push(appraise::appraise_measurement(
+                    source,
+                    measurement,
+                    &self.manifests,
+                ));
+            }
+        }
+
+        for (index, measurement) in log.iter().enumerate() {
+            let source = MeasurementSource::Log {
+                rot: self.rot(),
+                index,
+            };
+            report.measurements.push(appraise::appraise_measurement(
+                source,
+                measurement.into(),
+                &self.manifests,
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// `RotVerifier` for the VM instance "RoT" implemented by propolis. If
+/// propolis holds an `InstanceKey` this RoT has a cert chain made up of the
+/// instance cert followed by the cert chain from the Oxide platform RoT, and
+/// provides attestations signed by the instance key. The `VmInstanceConf`
+/// from its log & the `PlatformIdentity` of the platform RoT are evaluated
+/// against the `InstancePolicy`.
+pub struct OxideInstanceVerifier {
+    trust_store: Arc<TrustStore>,
+    policy: InstancePolicy,
+    revocation: RevocationList,
+}
+
+impl OxideInstanceVerifier {
+    pub fn new(trust_store: Arc<TrustStore>, policy: InstancePolicy) -> Self {
+        Self {
+            trust_store,
+            policy,
+            revocation: RevocationList::default(),
+        }
+    }
+
+    /// Reject cert chains w/ a cert revoked by one of the CRLs in
+    /// `revocation`.
+    pub fn with_revocation_list(mut self, revocation: RevocationList) -> Self {
+        self.revocation = revocation;
+        self
+    }
+}
+
+impl RotVerifier for OxideInstanceVerifier {
+    fn rot(&self) -> RotType {
+        RotType::OxideInstance
+    }
+
+    /// There's no cert chain unless propolis holds an `InstanceKey`.
+    fn verify_cert_chain(
+        &self,
+        cert_chain: Option<&CertChain>,
+        time: SystemTime,
+    ) -> Result<Option<VerifiedChain>, VerifyError> {
+        let pki_path = match cert_chain {
+            Some(cert_chain) => &cert_chain.pki_path,
+            None => return Ok(None),
+        };
+        let (instance_cert, platform_path) = pki_path
+            .split_first()
+            .filter(|(_, platform_path)| !platform_path.is_empty())
+            .ok_or(VerifyError::NoCertChain(self.rot()))?;
+
+        let anchor = self.trust_store.verify(platform_path)?;
+        instance_key::verify_cert_signature(instance_cert, &platform_path[0])?;
+        pki_policy::check_pki_path(platform_path)
+            .and_then(|()| pki_policy::check_instance_cert(instance_cert))
+            .map_err(|e| VerifyError::PkiPolicy(self.rot(), e))?;
+        self.revocation
+            .check(pki_path, std::slice::from_ref(&anchor.cert), time)
+            .map_err(|e| VerifyError::Revocation(self.rot(), e))?;
+        let platform = PlatformIdentity::from_pki_path(platform_path).ok();
+
+        Ok(Some(VerifiedChain {
+            rot: self.rot(),
+            trust_anchor: anchor.name,
+            platform,
+        }))
+    }
+
+    /// Without an `InstanceKey` this RoT doesn't sign attestations, its
+    /// measurement log is mixed into the data signed by the hardware RoTs.
+    fn verify_attestation(
+        &self,
+        attestation: Option<&Attestation>,
+        cert_chain: Option<&CertChain>,
+        log: Option<&MeasurementLog>,
+        data: &[u8; 32],
+    ) -> Result<(), VerifyError> {
+        let attestation = match attestation {
+            Some(attestation) => attestation,
+            None => return Ok(()),
+        };
+        let instance_cert = cert_chain
+            .and_then(|c| c.pki_path.first())
+            .ok_or(VerifyError::NoCertChain(self.rot()))?;
+        let log = log.ok_or(VerifyError::NoMeasurementLog(self.rot()))?;
+
+        instance_key::verify_signature(instance_cert, data, &attestation.data)?;
+
+        // the instance cert must have been issued for this instance
+        let cert_uuid = instance_key::cert_uuid(instance_cert)?;
+        match self.decode_log(log)? {
+            DecodedLog::OxideInstance(cfg) if cfg.uuid == cert_uuid => Ok(()),
+            DecodedLog::OxideInstance(cfg) => Err(InstanceKeyError::Uuid {
+                cert: cert_uuid,
+                log: cfg.uuid,
+            }
+            .into()),
+            _ => Err(AppraisalError::UnexpectedLog(self.rot()).into()),
+        }
+    }
+
+    /// The `PlatformIdentity` is taken from the platform part of this RoT's
+    /// cert chain, or the cert chain of the platform RoT if there's no
+    /// `InstanceKey`.
+    fn appraise_log(
+        &self,
+        log: &DecodedLog,
+        cert_chains: &[CertChain],
+        report: &mut AppraisalReport,
+    ) -> Result<(), AppraisalError> {
+        let platform_path = match find_cert_chain(cert_chains, self.rot()) {
+            Some(cert_chain) => cert_chain.pki_path.get(1..),
+            None => find_cert_chain(cert_chains, RotType::OxideHardware)
+                .map(|c| &c.pki_path[..]),
+        };
+        let platform = platform_path
+            .and_then(|path| PlatformIdentity::from_pki_path(path).ok());
+
+        match log {
+            DecodedLog::OxideInstance(_) if self.policy.rules.is_empty() => {
+                report.unappraised.push(self.rot());
+                Ok(())
+            }
+            DecodedLog::OxideInstance(instance_cfg) => {
+                report.instance.extend(
+                    self.policy.evaluate(instance_cfg, platform.as_ref()),
+                );
+                Ok(())
+            }
+            _ => Err(AppraisalError::UnexpectedLog(self.rot())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AttestationSigner, test::setup};
+
+    #[test]
+    fn register_replaces() {
+        let mut verifiers = RotVerifiers::default();
+        verifiers.register(Box::new(OxideInstanceVerifier::new(
+            Arc::default(),
+            InstancePolicy::default(),
+        )));
+        verifiers.register(Box::new(OxideInstanceVerifier::new(
+            Arc::default(),
+            InstancePolicy::default(),
+        )));
+
+        assert_eq!(verifiers.verifiers.len(), 1);
+        assert!(verifiers.get(RotType::OxideInstance).is_some());
+        assert!(verifiers.get(RotType::OxideHardware).is_none());
+    }
+
+    #[test]
+    fn unsupported_rot() {
+        let attest = setup();
+        let cert_chains = attest.get_cert_chains().expect("get_cert_chains");
+        let logs = attest.get_measurement_logs().expect("get_measurement_logs");
+
+        // no verifier registered for the hardware RoTs
+        let mut verifiers = RotVerifiers::default();
+        verifiers.register(Box::new(OxideInstanceVerifier::new(
+            Arc::default(),
+            InstancePolicy::default(),
+        )));
+
+        assert!(matches!(
+            verifiers.verify_cert_chains(&cert_chains, SystemTime::now()),
+            Err(VerifyError::UnsupportedRot(RotType::OxideHardware))
+        ));
+        assert!(matches!(
+            verifiers.appraise(&cert_chains, &logs),
+            Err(AppraisalError::UnsupportedRot(RotType::OxideHardware))
+        ));
+    }
+}
@@ -0,0 +1,339 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tamper detection tests for the verification path. Each test flips bits
+//! in one input to `Verifier::verify` and checks that verification fails
+//! w/ the error expected for that input. This guarantees that each input is
+//! bound into the data signed by the RoTs or checked against the PKI.
+
+use crate::{
+    AttestMock, AttestationSigner, MeasurementLog, Nonce, RotType,
+    VmInstanceConf,
+    appraise::AppraisalReport,
+    test::{setup, setup_instance_key, verifier_config},
+    verifier::{Evidence, Verifier, VerifyError},
+};
+use x509_cert::der::asn1::BitString;
+
+const USER_DATA: [u8; 32] = [0u8; 32];
+
+/// The maximum number of positions in a buffer to flip a bit at. Verifying
+/// the cert chains for each attempt is slow so we spread this many
+/// positions evenly across each buffer, always including the first & last
+/// bytes.
+const MAX_POSITIONS: usize = 16;
+
+fn positions(len: usize) -> Vec<usize> {
+    if len <= MAX_POSITIONS {
+        return (0..len).collect();
+    }
+
+    let mut positions: Vec<usize> = (0..MAX_POSITIONS - 1)
+        .map(|i| i * len / (MAX_POSITIONS - 1))
+        .collect();
+    positions.push(len - 1);
+
+    positions
+}
+
+/// Flip one bit in the byte at `position`. The bit flipped varies w/ the
+/// position.
+fn flip(data: &mut [u8], position: usize) {
+    data[position] ^= 1 << (position % 8);
+}
+
+fn flip_bit_string(bits: &BitString, position: usize) -> BitString {
+    let mut bytes = bits.raw_bytes().to_vec();
+    flip(&mut bytes, position);
+    BitString::new(bits.unused_bits(), bytes).expect("BitString")
+}
+
+/// Collect fresh evidence from `attest`, apply `tamper` & verify it.
+fn verify_tampered<F>(
+    attest: &AttestMock,
+    tamper: F,
+) -> Result<AppraisalReport, VerifyError>
+where
+    F: FnOnce(&mut Evidence),
+{
+    let verifier = Verifier::from_config(&verifier_config())
+        .expect("Verifier from config");
+    let nonce = verifier.issue_nonce().expect("issue nonce");
+    let mut evidence =
+        Evidence::collect(attest, &nonce, &USER_DATA).expect("evidence");
+
+    tamper(&mut evidence);
+
+    verifier.verify(&evidence, &nonce, &USER_DATA)
+}
+
+#[test]
+fn untampered() {
+    for attest in [setup(), setup_instance_key()] {
+        let report =
+            verify_tampered(&attest, |_| ()).expect("verify untampered");
+        assert!(report.is_ok(), "{report}");
+    }
+}
+
+#[test]
+fn tamper_attestation() {
+    let attest = setup();
+    let evidence =
+        Evidence::collect(&attest, &Nonce::from_array([0u8; 32]), &USER_DATA)
+            .expect("evidence");
+
+    for (index, attestation) in evidence.attestations.iter().enumerate() {
+        for position in positions(attestation.data.len()) {
+            let result = verify_tampered(&attest, |evidence| {
+                flip(&mut evidence.attestations[index].data, position)
+            });
+            // the first byte is the hubpack tag for the signature algorithm
+            if position == 0 {
+                assert!(
+                    matches!(result, Err(VerifyError::Deserialize)),
+                    "{:?} byte {position}",
+                    attestation.rot
+                );
+            } else {
+                assert!(
+                    matches!(result, Err(VerifyError::Attestation(_))),
+                    "{:?} byte {position}",
+                    attestation.rot
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn tamper_instance_attestation() {
+    let attest = setup_instance_key();
+    let evidence =
+        Evidence::collect(&attest, &Nonce::from_array([0u8; 32]), &USER_DATA)
+            .expect("evidence");
+    let index = evidence
+        .attestations
+        .iter()
+        .position(|a| a.rot == RotType::OxideInstance)
+        .expect("attestation from instance key");
+
+    for position in positions(evidence.attestations[index].data.len()) {
+        let result = verify_tampered(&attest, |evidence| {
+            flip(&mut evidence.attestations[index].data, position)
+        });
+        assert!(
+            matches!(result, Err(VerifyError::InstanceKey(_))),
+            "byte {position}"
+        );
+    }
+}
+
+#[test]
+fn tamper_hardware_log() {
+    let attest = setup();
+    let logs = attest.get_measurement_logs().expect("get_measurement_logs");
+
+    for (index, log) in logs.iter().enumerate() {
+        if log.rot == RotType::OxideInstance {
+            continue;
+        }
+        for position in positions(log.data.len()) {
+            let mut data = log.data.clone();
+            flip(&mut data, position);
+
+            // a log that still decodes must fail signature verification
+            let tampered = MeasurementLog { rot: log.rot, data };
+            let decodes = tampered.decode().is_ok();
+
+            let result = verify_tampered(&attest, |evidence| {
+                evidence.logs[index] = tampered
+            });
+            if decodes {
+                assert!(
+                    matches!(result, Err(VerifyError::Attestation(_))),
+                    "{:?} byte {position}",
+                    log.rot
+                );
+            } else {
+                assert!(
+                    matches!(result, Err(VerifyError::LogDecode(_))),
+                    "{:?} byte {position}",
+                    log.rot
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn tamper_hardware_log_instance_key() {
+    let attest = setup_instance_key();
+    let logs = attest.get_measurement_logs().expect("get_measurement_logs");
+    let index = logs
+        .iter()
+        .position(|l| l.rot == RotType::OxideHardware)
+        .expect("log from Oxide platform RoT");
+
+    // the Oxide platform RoT doesn't attest, its log is bound into the
+    // instance cert so even a log that still decodes is detected
+    for position in positions(logs[index].data.len()) {
+        let result = verify_tampered(&attest, |evidence| {
+            flip(&mut evidence.logs[index].data, position)
+        });
+        assert!(
+            matches!(result, Err(VerifyError::InstanceKey(_))),
+            "byte {position}"
+        );
+    }
+}
+
+#[test]
+fn tamper_instance_log() {
+    let attest = setup();
+    let logs = attest.get_measurement_logs().expect("get_measurement_logs");
+    let index = logs
+        .iter()
+        .position(|l| l.rot == RotType::OxideInstance)
+        .expect("instance log");
+
+    // the raw bytes of the instance log are digested so even changes that
+    // don't change the decoded log are detected
+    for position in positions(logs[index].data.len()) {
+        let result = verify_tampered(&attest, |evidence| {
+            flip(&mut evidence.logs[index].data, position)
+        });
+        assert!(
+            matches!(result, Err(VerifyError::Attestation(_))),
+            "byte {position}"
+        );
+    }
+}
+
+#[test]
+fn tamper_instance_conf() {
+    let attest = setup();
+
+    let tamper_fields: [fn(&mut VmInstanceConf); 3] = [
+        |cfg| {
+            let mut uuid = *cfg.uuid.as_bytes();
+            flip(&mut uuid, 0);
+            cfg.uuid = uuid::Uuid::from_bytes(uuid);
+        },
+        |cfg| {
+            let mut algorithm = cfg.image_digest.algorithm.clone().into_bytes();
+            flip(&mut algorithm, 0);
+            cfg.image_digest.algorithm =
+                String::from_utf8(algorithm).expect("ASCII algorithm");
+        },
+        |cfg| {
+            let mut digest = cfg.image_digest.digest.clone().into_bytes();
+            flip(&mut digest, 0);
+            cfg.image_digest.digest =
+                String::from_utf8(digest).expect("ASCII digest");
+        },
+    ];
+
+    for tamper_field in tamper_fields {
+        let result = verify_tampered(&attest, |evidence| {
+            let log = evidence
+                .logs
+                .iter_mut()
+                .find(|l| l.rot == RotType::OxideInstance)
+                .expect("instance log");
+            let mut cfg: VmInstanceConf =
+                serde_json::from_slice(&log.data).expect("decode instance log");
+            tamper_field(&mut cfg);
+            log.data = serde_json::to_vec(&cfg).expect("encode instance log");
+        });
+        assert!(matches!(result, Err(VerifyError::Attestation(_))));
+    }
+}
+
+#[test]
+fn tamper_cert_chain() {
+    let attest = setup_instance_key();
+    let cert_chains = attest.get_cert_chains().expect("get_cert_chains");
+
+    for (chain, cert_chain) in cert_chains.iter().enumerate() {
+        for cert in 0..cert_chain.pki_path.len() {
+            // the instance cert is verified by the instance key code, the
+            // rest by the dice verifier
+            let expected = |result: &Result<_, VerifyError>| {
+                if cert_chain.rot == RotType::OxideInstance && cert == 0 {
+                    matches!(result, Err(VerifyError::InstanceKey(_)))
+                } else {
+                    matches!(result, Err(VerifyError::CertChain(_)))
+                }
+            };
+
+            let result = verify_tampered(&attest, |evidence| {
+                let cert = &mut evidence.cert_chains[chain].pki_path[cert];
+                cert.signature = flip_bit_string(&cert.signature, 0);
+            });
+            assert!(
+                expected(&result),
+                "signature: {:?} cert {cert}",
+                cert_chain.rot
+            );
+
+            let result = verify_tampered(&attest, |evidence| {
+                let spki = &mut evidence.cert_chains[chain].pki_path[cert]
+                    .tbs_certificate
+                    .subject_public_key_info;
+                spki.subject_public_key =
+                    flip_bit_string(&spki.subject_public_key, 0);
+            });
+            assert!(
+                expected(&result),
+                "public key: {:?} cert {cert}",
+                cert_chain.rot
+            );
+        }
+    }
+}
+
+#[test]
+fn tamper_nonce() {
+    let attest = setup();
+    let verifier = Verifier::from_config(&verifier_config())
+        .expect("Verifier from config");
+
+    // a nonce that wasn't issued is rejected before the evidence is checked
+    let nonce = verifier.issue_nonce().expect("issue nonce");
+    let evidence =
+        Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+    let mut tampered: [u8; 32] =
+        nonce.as_ref().try_into().expect("32 byte nonce");
+    flip(&mut tampered, 0);
+    let result =
+        verifier.verify(&evidence, &Nonce::from_array(tampered), &USER_DATA);
+    assert!(matches!(result, Err(VerifyError::UnknownNonce)));
+
+    // an issued nonce must be the one the evidence was produced for
+    let other = verifier.issue_nonce().expect("issue nonce");
+    let result = verifier.verify(&evidence, &other, &USER_DATA);
+    assert!(matches!(result, Err(VerifyError::Attestation(_))));
+}
+
+#[test]
+fn tamper_user_data() {
+    let attest = setup();
+    let verifier = Verifier::from_config(&verifier_config())
+        .expect("Verifier from config");
+
+    for position in positions(USER_DATA.len()) {
+        let nonce = verifier.issue_nonce().expect("issue nonce");
+        let evidence =
+            Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+
+        let mut user_data = USER_DATA;
+        flip(&mut user_data, position);
+        let result = verifier.verify(&evidence, &nonce, &user_data);
+        assert!(
+            matches!(result, Err(VerifyError::Attestation(_))),
+            "byte {position}"
+        );
+    }
+}
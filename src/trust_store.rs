@@ -0,0 +1,437 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The root certs trusted by a verifier. A verifier may trust several roots
+//! at once (e.g. the current & next root for the platform PKI, or a root
+//! per environment) and roots can be added & removed while it's running.
+//!
+//! Roots are rotated in stages: the next root is added as
+//! `AnchorState::Staged`, activated once it's been distributed to every
+//! verifier, and the old root is removed once no cert chain depends on it.
+//! Chains rooted at a staged anchor are rejected w/
+//! `VerifyError::StagedAnchor` so that a rollout can be observed before
+//! it's enabled.
+
+use crate::verifier::VerifyError;
+use der::Decode;
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+use x509_cert::Certificate;
+
+/// The subdirectory of a trust anchor directory holding staged anchors.
+pub const STAGED_DIR: &str = "staged";
+
+/// The file extensions for certs loaded from a trust anchor directory.
+const CERT_EXTENSIONS: [&str; 4] = ["pem", "crt", "cer", "der"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum TrustStoreError {
+    #[error("failed to read trust anchor: {0}")]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("failed to parse trust anchor: {0}")]
+    Der(PathBuf, #[source] der::Error),
+    #[error("trust anchor already in store: {0}")]
+    Duplicate(String),
+    #[error("no trust anchor named: {0}")]
+    UnknownAnchor(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnchorState {
+    /// Cert chains rooted at this anchor are accepted.
+    Active,
+    /// The anchor is known but cert chains rooted at it aren't accepted
+    /// until it's activated.
+    Staged,
+}
+
+/// A root cert & the name used to refer to it when it's reported or
+/// rotated. Anchors loaded from a file are named by its path, as passed to
+/// `TrustStore::from_paths` or joined to the directory that holds it (e.g.
+/// `trust/staged/next.pem`), so anchors from different directories or
+/// states can't collide.
+#[derive(Clone, Debug)]
+pub struct TrustAnchor {
+    pub name: String,
+    pub state: AnchorState,
+    pub cert: Certificate,
+}
+
+/// A set of trust anchors shared by the `RotVerifier`s for a PKI.
+#[derive(Debug, Default)]
+pub struct TrustStore {
+    anchors: RwLock<Vec<TrustAnchor>>,
+}
+
+impl From<Vec<Certificate>> for TrustStore {
+    /// Trust each of `certs`, named by position.
+    fn from(certs: Vec<Certificate>) -> Self {
+        let anchors = certs
+            .into_iter()
+            .enumerate()
+            .map(|(index, cert)| TrustAnchor {
+                name: format!("trust-anchor-{index}"),
+                state: AnchorState::Active,
+                cert,
+            })
+            .collect();
+
+        Self {
+            anchors: RwLock::new(anchors),
+        }
+    }
+}
+
+impl TrustStore {
+    /// Load the anchors from each of `paths`. A path may be a file holding
+    /// one or more DER or PEM encoded certs, or a directory of them. Certs
+    /// in the `staged` subdirectory of a directory are staged, all others
+    /// are active.
+    pub fn from_paths<P: AsRef<Path>>(
+        paths: &[P],
+    ) -> Result<Self, TrustStoreError> {
+        let store = Self::default();
+        for path in paths {
+            let path = path.as_ref();
+            if path.is_dir() {
+                store.add_dir(path)?;
+            } else {
+                for anchor in load_file(path, AnchorState::Active)? {
+                    store.add(anchor)?;
+                }
+            }
+        }
+
+        Ok(store)
+    }
+
+    /// Load the anchors from the directory `dir`.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<Self, TrustStoreError> {
+        Self::from_paths(&[dir])
+    }
+
+    fn add_dir(&self, dir: &Path) -> Result<(), TrustStoreError> {
+        for (path, state) in cert_files(dir)?
+            .into_iter()
+            .map(|p| (p, AnchorState::Active))
+            .chain(
+                cert_files(&dir.join(STAGED_DIR))?
+                    .into_iter()
+                    .map(|p| (p, AnchorState::Staged)),
+            )
+        {
+            for anchor in load_file(&path, state)? {
+                self.add(anchor)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add `anchor` to the store. Names must be unique.
+    pub fn add(&self, anchor: TrustAnchor) -> Result<(), TrustStoreError> {
+        let mut anchors = self.write();
+        if anchors.iter().any(|a| a.name == anchor.name) {
+            return Err(TrustStoreError::Duplicate(anchor.name));
+        }
+        anchors.push(anchor);
+
+        Ok(())
+    }
+
+    /// Add `cert` as a staged anchor named `name`.
+    pub fn stage(
+        &self,
+        name: &str,
+        cert: Certificate,
+    ) -> Result<(), TrustStoreError> {
+        self.add(TrustAnchor {
+            name: name.to_string(),
+            state: AnchorState::Staged,
+            cert,
+        })
+    }
+
+    /// Accept cert chains rooted at the staged anchor `name`.
+    pub fn activate(&self, name: &str) -> Result<(), TrustStoreError> {
+        let mut anchors = self.write();
+        let anchor = anchors
+            .iter_mut()
+            .find(|a| a.name == name)
+            .ok_or_else(|| TrustStoreError::UnknownAnchor(name.to_string()))?;
+        anchor.state = AnchorState::Active;
+
+        Ok(())
+    }
+
+    /// Remove the anchor `name` from the store.
+    pub fn remove(&self, name: &str) -> Result<TrustAnchor, TrustStoreError> {
+        let mut anchors = self.write();
+        let index = anchors
+            .iter()
+            .position(|a| a.name == name)
+            .ok_or_else(|| TrustStoreError::UnknownAnchor(name.to_string()))?;
+
+        Ok(anchors.remove(index))
+    }
+
+    /// A copy of every anchor in the store.
+    pub fn anchors(&self) -> Vec<TrustAnchor> {
+        self.read().clone()
+    }
+
+    /// The certs for the anchors in `state`.
+    pub fn certs(&self, state: AnchorState) -> Vec<Certificate> {
+        self.read()
+            .iter()
+            .filter(|a| a.state == state)
+            .map(|a| a.cert.clone())
+            .collect()
+    }
+
+    /// Verify `pki_path` against the active anchors returning the anchor
+    /// that validated it.
+    pub fn verify(
+        &self,
+        pki_path: &[Certificate],
+    ) -> Result<TrustAnchor, VerifyError> {
+        let pki_path = pki_path.to_vec();
+        let active = self.certs(AnchorState::Active);
+        let error =
+            match dice_verifier::verify_cert_chain(&pki_path, Some(&active)) {
+                Ok(root) => return Ok(self.find(root)),
+                Err(e) => e,
+            };
+
+        // report chains that will be accepted once an anchor is activated
+        let staged = self.certs(AnchorState::Staged);
+        if staged.is_empty() {
+            return Err(error.into());
+        }
+        match dice_verifier::verify_cert_chain(&pki_path, Some(&staged)) {
+            Ok(root) => Err(VerifyError::StagedAnchor(self.find(root).name)),
+            Err(_) => Err(error.into()),
+        }
+    }
+
+    /// The anchor holding `cert`. If the anchor was removed since `cert` was
+    /// copied out of the store it's named by the cert subject.
+    fn find(&self, cert: &Certificate) -> TrustAnchor {
+        self.read()
+            .iter()
+            .find(|a| &a.cert == cert)
+            .cloned()
+            .unwrap_or_else(|| TrustAnchor {
+                name: cert.tbs_certificate.subject.to_string(),
+                state: AnchorState::Active,
+                cert: cert.clone(),
+            })
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Vec<TrustAnchor>> {
+        self.anchors.read().expect("trust store lock poisoned")
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, Vec<TrustAnchor>> {
+        self.anchors.write().expect("trust store lock poisoned")
+    }
+}
+
+/// The cert files in `dir`, sorted by name. A missing directory has none.
+fn cert_files(dir: &Path) -> Result<Vec<PathBuf>, TrustStoreError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Vec::new());
+        }
+        Err(e) => return Err(TrustStoreError::Io(dir.to_path_buf(), e)),
+    };
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|e| TrustStoreError::Io(dir.to_path_buf(), e))?
+            .path();
+        let is_cert = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| CERT_EXTENSIONS.contains(&e));
+        if path.is_file() && is_cert {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Load the DER or PEM encoded certs from `path`. The anchors are named
+/// by the path, w/ the position of the cert appended if there's more than
+/// one.
+fn load_file(
+    path: &Path,
+    state: AnchorState,
+) -> Result<Vec<TrustAnchor>, TrustStoreError> {
+    let bytes = fs::read(path)
+        .map_err(|e| TrustStoreError::Io(path.to_path_buf(), e))?;
+    let certs = if bytes.starts_with(b"-----BEGIN") {
+        Certificate::load_pem_chain(&bytes)
+    } else {
+        Certificate::from_der(&bytes).map(|cert| vec![cert])
+    }
+    .map_err(|e| TrustStoreError::Der(path.to_path_buf(), e))?;
+
+    let name = path.display().to_string();
+    let count = certs.len();
+
+    Ok(certs
+        .into_iter()
+        .enumerate()
+        .map(|(index, cert)| TrustAnchor {
+            name: match count {
+                1 => name.clone(),
+                _ => format!("{name}#{index}"),
+            },
+            state,
+            cert,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        AttestationSigner,
+        test::{config, setup},
+    };
+    use der::Encode;
+
+    /// A directory w/ the platform root staged & the foreign root active.
+    fn trust_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("trust-store-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join(STAGED_DIR)).expect("create trust dir");
+
+        fs::copy(config::PKI_ROOT, dir.join(STAGED_DIR).join("next.pem"))
+            .expect("copy platform root");
+        let foreign = fs::read(config::FOREIGN_PKIPATH).expect("read foreign");
+        let foreign =
+            Certificate::load_pem_chain(&foreign).expect("parse foreign");
+        let root = foreign.last().expect("foreign root");
+        fs::write(dir.join("current.der"), root.to_der().expect("encode"))
+            .expect("write foreign root");
+        // files that aren't certs are ignored
+        fs::write(dir.join("README"), "not a cert").expect("write README");
+
+        dir
+    }
+
+    /// The name of the anchor loaded from `file` in the trust dir `dir`.
+    fn anchor_name(dir: &Path, file: &str) -> String {
+        dir.join(file).display().to_string()
+    }
+
+    #[test]
+    fn load_dir() {
+        let dir = trust_dir("load");
+        let store = TrustStore::from_dir(&dir).expect("load trust dir");
+        let _ = fs::remove_dir_all(&dir);
+
+        let anchors: Vec<_> = store
+            .anchors()
+            .into_iter()
+            .map(|a| (a.name, a.state))
+            .collect();
+        assert_eq!(
+            anchors,
+            vec![
+                (anchor_name(&dir, "current.der"), AnchorState::Active),
+                (anchor_name(&dir, "staged/next.pem"), AnchorState::Staged),
+            ]
+        );
+    }
+
+    #[test]
+    fn load_dirs_same_names() {
+        // the same file names in two dirs & in the staged subdir of a dir
+        let first = trust_dir("same-names-first");
+        let second = trust_dir("same-names-second");
+        fs::copy(
+            first.join(STAGED_DIR).join("next.pem"),
+            first.join("next.pem"),
+        )
+        .expect("copy staged anchor");
+
+        let store = TrustStore::from_paths(&[&first, &second])
+            .expect("load trust dirs");
+        let _ = fs::remove_dir_all(&first);
+        let _ = fs::remove_dir_all(&second);
+
+        let mut names: Vec<_> =
+            store.anchors().into_iter().map(|a| a.name).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                anchor_name(&first, "current.der"),
+                anchor_name(&first, "next.pem"),
+                anchor_name(&first, "staged/next.pem"),
+                anchor_name(&second, "current.der"),
+                anchor_name(&second, "staged/next.pem"),
+            ]
+        );
+    }
+
+    #[test]
+    fn staged_rotation() {
+        let dir = trust_dir("rotation");
+        let store = TrustStore::from_dir(&dir).expect("load trust dir");
+        let _ = fs::remove_dir_all(&dir);
+        let cert_chains = setup().get_cert_chains().expect("get_cert_chains");
+        let pki_path = &cert_chains[0].pki_path;
+        let current = anchor_name(&dir, "current.der");
+        let next = anchor_name(&dir, "staged/next.pem");
+
+        // the platform root is staged
+        let result = store.verify(pki_path);
+        assert!(
+            matches!(result, Err(VerifyError::StagedAnchor(ref name)) if *name == next)
+        );
+
+        store.activate(&next).expect("activate next");
+        let anchor = store.verify(pki_path).expect("verify w/ next");
+        assert_eq!(anchor.name, next);
+
+        store.remove(&current).expect("retire current");
+        assert!(store.remove(&current).is_err());
+        store.verify(pki_path).expect("verify after rotation");
+
+        store.remove(&next).expect("remove next");
+        assert!(matches!(
+            store.verify(pki_path),
+            Err(VerifyError::CertChain(_))
+        ));
+    }
+
+    #[test]
+    fn duplicate_anchor() {
+        let store = TrustStore::from_paths(&[config::PKI_ROOT])
+            .expect("load platform root");
+        let root = store.anchors().remove(0);
+        assert_eq!(root.name, config::PKI_ROOT);
+
+        let result = store.stage(&root.name, root.cert.clone());
+        assert!(matches!(result, Err(TrustStoreError::Duplicate(_))));
+        store
+            .stage("next", root.cert)
+            .expect("stage under a new name");
+    }
+}
@@ -0,0 +1,1148 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{
+    Attestation, AttestationSigner, CertChain, LogDecodeError, MeasurementLog,
+    Nonce, RotType,
+    appraise::{AppraisalError, AppraisalReport, ReferenceManifest},
+    instance_key::InstanceKeyError,
+    merkle::MerkleError,
+    nonce_store::{
+        FileNonceStore, MemoryNonceStore, NonceStore, NonceStoreError,
+    },
+    pki_policy::PkiPolicyError,
+    policy::{InstancePolicy, PolicyError},
+    revocation::{RevocationError, RevocationList},
+    rot::{
+        OxideHardwareVerifier, OxideInstanceVerifier, RotVerifiers,
+        VerifiedChain,
+    },
+    trust_store::{AnchorState, TrustStore, TrustStoreError},
+};
+use dice_verifier::{PkiPathSignatureVerifierError, VerifyAttestationError};
+use rats_corim::Corim;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use x509_cert::time::Time;
+
+/// Errors produced while loading a `VerifierConfig` or constructing a
+/// `Verifier` from one.
+#[derive(Debug, thiserror::Error)]
+pub enum VerifierConfigError {
+    #[error("failed to read file: {0}")]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("failed to parse KDL verifier config")]
+    Kdl(#[from] knuffel::Error),
+    #[error("failed to parse JSON verifier config")]
+    Json(#[from] serde_json::Error),
+    #[error("unsupported verifier config format: {0}")]
+    Format(PathBuf),
+    #[error("unknown nonce store kind: {0}")]
+    NonceStoreKind(String),
+    #[error("nonce store kind \"file\" requires a path")]
+    NonceStorePath,
+    #[error("verifier config has no freshness-window")]
+    NoFreshnessWindow,
+    #[error("no active trust anchors in verifier config")]
+    NoTrustAnchors,
+    #[error("failed to load trust anchors")]
+    TrustStore(#[from] TrustStoreError),
+    #[error("failed to load CoRIM: {0}")]
+    Corim(PathBuf, #[source] rats_corim::Error),
+    #[error("failed to get reference measurements from CoRIM")]
+    ReferenceManifest(#[from] AppraisalError),
+    #[error("failed to load instance policy")]
+    Policy(#[from] PolicyError),
+    #[error("failed to load CRL")]
+    Revocation(#[from] RevocationError),
+}
+
+/// Errors produced while verifying `Evidence`.
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyError {
+    #[error("failed to generate nonce: {0}")]
+    Rng(getrandom::Error),
+    #[error("nonce store error")]
+    NonceStore(#[from] NonceStoreError),
+    #[error("nonce was not issued by this verifier or has already been used")]
+    UnknownNonce,
+    #[error("nonce was issued {0:?} ago, outside of the freshness window")]
+    StaleNonce(Duration),
+    #[error("no RotVerifier registered for {0:?}")]
+    UnsupportedRot(RotType),
+    #[error("no cert chain for {0:?}")]
+    NoCertChain(RotType),
+    #[error("no measurement log for {0:?}")]
+    NoMeasurementLog(RotType),
+    #[error("no attestation from {0:?}")]
+    NoAttestation(RotType),
+    #[error("more than one attestation from {0:?}")]
+    DuplicateAttestation(RotType),
+    #[error("more than one cert chain for {0:?}")]
+    DuplicateCertChain(RotType),
+    #[error("more than one measurement log from {0:?}")]
+    DuplicateLog(RotType),
+    #[error("attestations are not in the relative order of their cert chains")]
+    AttestationOrder,
+    #[error("failed to verify cert chain")]
+    CertChain(#[from] PkiPathSignatureVerifierError),
+    #[error("cert chain is rooted at the staged trust anchor {0}")]
+    StagedAnchor(String),
+    #[error("cert chain for {0:?} violates the platform PKI policy")]
+    PkiPolicy(RotType, #[source] PkiPolicyError),
+    #[error(
+        "cert {index} in the chain for {rot:?} not valid until {not_before}"
+    )]
+    CertNotYetValid {
+        rot: RotType,
+        index: usize,
+        not_before: Time,
+    },
+    #[error("cert {index} in the chain for {rot:?} expired at {not_after}")]
+    CertExpired {
+        rot: RotType,
+        index: usize,
+        not_after: Time,
+    },
+    #[error("revocation check failed for the cert chain for {0:?}")]
+    Revocation(RotType, #[source] RevocationError),
+    #[error("failed to deserialize hubpacked attestation")]
+    Deserialize,
+    #[error("failed to decode measurement log")]
+    LogDecode(#[from] LogDecodeError),
+    #[error("failed to verify attestation")]
+    Attestation(#[from] VerifyAttestationError),
+    #[error("failed to verify instance key")]
+    InstanceKey(#[from] InstanceKeyError),
+    #[error("malformed inclusion proof")]
+    InclusionProof(#[from] MerkleError),
+    #[error("failed to appraise measurements")]
+    Appraisal(#[from] AppraisalError),
+}
+
+/// Where the verifier records the nonces it has issued.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum NonceStoreConfig {
+    /// Keep issued nonces in memory.
+    #[default]
+    Memory,
+    /// Keep issued nonces in a file.
+    File { path: PathBuf },
+}
+
+/// Configuration for a `Verifier`. This can be loaded from either KDL:
+///
+/// ```kdl
+/// trust-anchor "root.cert.pem"
+/// corim "corim.cbor"
+/// crl "device-id.crl.pem"
+/// instance-policy "instance-policy.kdl"
+/// freshness-window 300
+/// nonce-store "file" path="nonces.json"
+/// ```
+///
+/// or the equivalent JSON:
+///
+/// ```json
+/// {
+///     "trust-anchors": [ "root.cert.pem" ],
+///     "corims": [ "corim.cbor" ],
+///     "crls": [ "device-id.crl.pem" ],
+///     "instance-policy": "instance-policy.kdl",
+///     "freshness-window": 300,
+///     "nonce-store": { "kind": "file", "path": "nonces.json" }
+/// }
+/// ```
+///
+/// Relative paths are resolved relative to the directory holding the config
+/// file. The freshness window is in seconds.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct VerifierConfig {
+    /// DER or PEM encoded root certificates for the platform PKI, or
+    /// directories of them (see `TrustStore::from_paths`).
+    pub trust_anchors: Vec<PathBuf>,
+    /// CBOR encoded CoRIM documents used to appraise measurements.
+    #[serde(default)]
+    pub corims: Vec<PathBuf>,
+    /// DER or PEM encoded CRLs from the CAs in the platform PKI.
+    #[serde(default)]
+    pub crls: Vec<PathBuf>,
+    /// KDL policy used to appraise the `VmInstanceConf`. If omitted the
+    /// instance config is reported as unappraised, so no `AppraisalReport`
+    /// is ok.
+    #[serde(default)]
+    pub instance_policy: Option<PathBuf>,
+    /// The maximum time in seconds between issuing a nonce & verifying the
+    /// evidence that includes it.
+    pub freshness_window: u64,
+    #[serde(default)]
+    pub nonce_store: NonceStoreConfig,
+}
+
+#[derive(knuffel::Decode, Debug)]
+struct PathNode {
+    #[knuffel(argument)]
+    path: String,
+}
+
+#[derive(knuffel::Decode, Debug)]
+struct SecondsNode {
+    #[knuffel(argument)]
+    secs: u64,
+}
+
+#[derive(knuffel::Decode, Debug)]
+struct NonceStoreNode {
+    #[knuffel(argument)]
+    kind: String,
+    #[knuffel(property)]
+    path: Option<String>,
+}
+
+#[derive(knuffel::Decode, Debug)]
+enum ConfigNode {
+    TrustAnchor(PathNode),
+    Corim(PathNode),
+    Crl(PathNode),
+    InstancePolicy(PathNode),
+    FreshnessWindow(SecondsNode),
+    NonceStore(NonceStoreNode),
+}
+
+impl VerifierConfig {
+    /// Parse a config from KDL. `name` is used in error messages.
+    pub fn from_kdl(
+        name: &str,
+        text: &str,
+    ) -> Result<Self, VerifierConfigError> {
+        let nodes: Vec<ConfigNode> = knuffel::parse(name, text)?;
+
+        let mut config = Self {
+            trust_anchors: Vec::new(),
+            corims: Vec::new(),
+            crls: Vec::new(),
+            instance_policy: None,
+            freshness_window: 0,
+            nonce_store: NonceStoreConfig::default(),
+        };
+        let mut freshness_window = None;
+        for node in nodes {
+            match node {
+                ConfigNode::TrustAnchor(node) => {
+                    config.trust_anchors.push(node.path.into())
+                }
+                ConfigNode::Corim(node) => config.corims.push(node.path.into()),
+                ConfigNode::Crl(node) => config.crls.push(node.path.into()),
+                ConfigNode::InstancePolicy(node) => {
+                    config.instance_policy = Some(node.path.into())
+                }
+                ConfigNode::FreshnessWindow(node) => {
+                    freshness_window = Some(node.secs)
+                }
+                ConfigNode::NonceStore(node) => {
+                    config.nonce_store = match node.kind.as_str() {
+                        "memory" => NonceStoreConfig::Memory,
+                        "file" => NonceStoreConfig::File {
+                            path: node
+                                .path
+                                .ok_or(VerifierConfigError::NonceStorePath)?
+                                .into(),
+                        },
+                        _ => {
+                            return Err(VerifierConfigError::NonceStoreKind(
+                                node.kind,
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        // required, as in JSON: a window of 0 makes every nonce stale
+        config.freshness_window =
+            freshness_window.ok_or(VerifierConfigError::NoFreshnessWindow)?;
+
+        Ok(config)
+    }
+
+    /// Parse a config from JSON.
+    pub fn from_json(text: &str) -> Result<Self, VerifierConfigError> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    /// Load a config from a file. The format is determined by the file
+    /// extension: `.kdl` or `.json`. Relative paths in the config are
+    /// resolved relative to the directory containing the file.
+    pub fn from_file<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<Self, VerifierConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| VerifierConfigError::Io(path.to_path_buf(), e))?;
+
+        let config = match path.extension().and_then(|e| e.to_str()) {
+            Some("kdl") => Self::from_kdl(&path.display().to_string(), &text)?,
+            Some("json") => Self::from_json(&text)?,
+            _ => return Err(VerifierConfigError::Format(path.to_path_buf())),
+        };
+
+        match path.parent() {
+            Some(dir) => Ok(config.relative_to(dir)),
+            None => Ok(config),
+        }
+    }
+
+    /// Resolve all relative paths in the config relative to `dir`.
+    fn relative_to(mut self, dir: &Path) -> Self {
+        let resolve = |path: &mut PathBuf| {
+            if path.is_relative() {
+                *path = dir.join(&*path);
+            }
+        };
+
+        self.trust_anchors.iter_mut().for_each(resolve);
+        self.corims.iter_mut().for_each(resolve);
+        self.crls.iter_mut().for_each(resolve);
+        self.instance_policy.iter_mut().for_each(resolve);
+        if let NonceStoreConfig::File { path } = &mut self.nonce_store {
+            resolve(path);
+        }
+
+        self
+    }
+}
+
+/// The data produced by an `AttestationSigner` in response to a challenge.
+/// The JSON encoding of this type is used as the bundle that carries the
+/// evidence from the challenger to the verifier.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Evidence {
+    /// At most one attestation per RoT, in the relative order of their cert
+    /// chains in `cert_chains`: RoTs w/o an attestation are skipped. Every
+    /// RoT w/ a cert chain must attest unless its chain is endorsed by a RoT
+    /// that does, e.g. an `OxideInstance` attestation from an `InstanceKey`
+    /// in place of one from `OxideHardware`.
+    pub attestations: Vec<Attestation>,
+    pub logs: Vec<MeasurementLog>,
+    pub cert_chains: Vec<CertChain>,
+}
+
+/// `Evidence` along w/ the nonce & user data that it was produced for, as
+/// written to disk by the `vm-attest` CLI.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EvidenceBundle {
+    pub nonce: Nonce,
+    #[serde(with = "crate::hex_bytes")]
+    pub user_data: Vec<u8>,
+    pub evidence: Evidence,
+}
+
+impl Evidence {
+    /// Collect the attestations, measurement logs & cert chains from the
+    /// provided `AttestationSigner`.
+    pub fn collect<S: AttestationSigner>(
+        signer: &S,
+        nonce: &Nonce,
+        user_data: &[u8],
+    ) -> Result<Self, S::Error> {
+        Ok(Self {
+            attestations: signer.attest(nonce, user_data)?,
+            logs: signer.get_measurement_logs()?,
+            cert_chains: signer.get_cert_chains()?,
+        })
+    }
+}
+
+/// The source of the current time for a `Verifier`. This is used to check
+/// the freshness of nonces & the validity period of each cert.
+pub trait WallClock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// A `WallClock` backed by the system clock.
+#[derive(Debug, Default)]
+pub struct SystemWallClock;
+
+impl WallClock for SystemWallClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A `WallClock` stopped at a fixed time.
+#[derive(Debug)]
+pub struct FixedClock(pub SystemTime);
+
+impl WallClock for FixedClock {
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+}
+
+/// A relying party. The verifier issues nonces to challengers & verifies
+/// the evidence they return: the cert chains and the attestations are
+/// verified by the `RotVerifier` for each RoT, and the measurement logs are
+/// appraised by the `RotVerifier` for each RoT producing an
+/// `AppraisalReport`.
+pub struct Verifier {
+    rot_verifiers: RotVerifiers,
+    freshness_window: Duration,
+    nonce_store: Box<dyn NonceStore>,
+    clock: Arc<dyn WallClock>,
+    trust_store: Option<Arc<TrustStore>>,
+}
+
+impl Verifier {
+    pub fn new(
+        rot_verifiers: RotVerifiers,
+        freshness_window: Duration,
+        nonce_store: Box<dyn NonceStore>,
+    ) -> Self {
+        Self {
+            rot_verifiers,
+            freshness_window,
+            nonce_store,
+            clock: Arc::new(SystemWallClock),
+            trust_store: None,
+        }
+    }
+
+    /// The `TrustStore` shared by the `RotVerifier`s, if the verifier was
+    /// constructed from a `VerifierConfig`. Anchors added to or removed from
+    /// the store take effect for the next verification.
+    pub fn trust_store(&self) -> Option<&Arc<TrustStore>> {
+        self.trust_store.as_ref()
+    }
+
+    /// Use `clock` in place of the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn WallClock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Load all of the files referenced by the config & construct a
+    /// `Verifier`.
+    pub fn from_config(
+        config: &VerifierConfig,
+    ) -> Result<Self, VerifierConfigError> {
+        let trust_store = TrustStore::from_paths(&config.trust_anchors)?;
+        if trust_store.certs(AnchorState::Active).is_empty() {
+            return Err(VerifierConfigError::NoTrustAnchors);
+        }
+        let trust_store = Arc::new(trust_store);
+
+        let mut manifests = Vec::new();
+        for path in &config.corims {
+            let corim = Corim::from_file(path)
+                .map_err(|e| VerifierConfigError::Corim(path.clone(), e))?;
+            manifests.extend(ReferenceManifest::from_corim(&corim)?);
+        }
+
+        let revocation = RevocationList::from_files(&config.crls)?;
+
+        let instance_policy = match &config.instance_policy {
+            Some(path) => InstancePolicy::from_file(path)?,
+            None => InstancePolicy::default(),
+        };
+
+        let nonce_store: Box<dyn NonceStore> = match &config.nonce_store {
+            NonceStoreConfig::Memory => Box::new(MemoryNonceStore::default()),
+            NonceStoreConfig::File { path } => {
+                Box::new(FileNonceStore::new(path.clone()))
+            }
+        };
+
+        // the platform RoT, the SP RoT & the instance key share the platform
+        // PKI & its CRLs, the hardware RoTs share the reference manifests
+        let mut rot_verifiers = RotVerifiers::default();
+        rot_verifiers.register(Box::new(
+            OxideHardwareVerifier::new(
+                RotType::OxideSp,
+                trust_store.clone(),
+                manifests.clone(),
+            )
+            .with_revocation_list(revocation.clone()),
+        ));
+        rot_verifiers.register(Box::new(
+            OxideInstanceVerifier::new(trust_store.clone(), instance_policy)
+                .with_revocation_list(revocation.clone()),
+        ));
+        rot_verifiers.register(Box::new(
+            OxideHardwareVerifier::new(
+                RotType::OxideHardware,
+                trust_store.clone(),
+                manifests,
+            )
+            .with_revocation_list(revocation),
+        ));
+
+        Ok(Self {
+            trust_store: Some(trust_store),
+            ..Self::new(
+                rot_verifiers,
+                Duration::from_secs(config.freshness_window),
+                nonce_store,
+            )
+        })
+    }
+
+    /// Load a `VerifierConfig` from a file & construct a `Verifier` from it.
+    pub fn from_config_file<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<Self, VerifierConfigError> {
+        Self::from_config(&VerifierConfig::from_file(path)?)
+    }
+
+    /// Generate a nonce for a challenger. The nonce is recorded in the
+    /// `NonceStore` and must be used within the freshness window. Expired
+    /// nonces are pruned from the store as a side effect.
+    pub fn issue_nonce(&self) -> Result<Nonce, VerifyError> {
+        let now = self.clock.now();
+        if let Some(expired) = now.checked_sub(self.freshness_window) {
+            self.nonce_store.prune(expired)?;
+        }
+
+        let nonce = Nonce::from_platform_rng().map_err(VerifyError::Rng)?;
+        self.nonce_store.insert(&nonce, now)?;
+
+        Ok(nonce)
+    }
+
+    /// Consume the nonce from the store & check that it's fresh.
+    fn check_nonce(&self, nonce: &Nonce) -> Result<(), VerifyError> {
+        let issued = self
+            .nonce_store
+            .take(nonce)?
+            .ok_or(VerifyError::UnknownNonce)?;
+        // a nonce from the future is treated as brand new
+        let age = self
+            .clock
+            .now()
+            .duration_since(issued)
+            .unwrap_or(Duration::ZERO);
+        if age > self.freshness_window {
+            return Err(VerifyError::StaleNonce(age));
+        }
+
+        Ok(())
+    }
+
+    /// Verify the `Evidence` produced in response to `nonce` and
+    /// `user_data`. The nonce must have been issued by this verifier. An
+    /// `Ok` result means the evidence is authentic, the caller must still
+    /// check `AppraisalReport::is_ok` to decide whether it's trustworthy.
+    pub fn verify(
+        &self,
+        evidence: &Evidence,
+        nonce: &Nonce,
+        user_data: &[u8],
+    ) -> Result<AppraisalReport, VerifyError> {
+        self.check_nonce(nonce)?;
+        let cert_chains = self.verify_evidence(evidence, nonce, user_data)?;
+
+        let mut report = self.appraise(evidence)?;
+        report.cert_chains = cert_chains;
+
+        Ok(report)
+    }
+
+    /// Verify the cert chains & attestations from `evidence` produced in
+    /// response to `nonce` and `user_data`. Each cert must be valid at the
+    /// current time. Returns the trust anchor that validated each cert
+    /// chain.
+    ///
+    /// NOTE: This does not check that the nonce was issued by this verifier
+    /// or that it's fresh. It's intended for offline verification of saved
+    /// evidence, a relying party should use `verify`.
+    pub fn verify_evidence(
+        &self,
+        evidence: &Evidence,
+        nonce: &Nonce,
+        user_data: &[u8],
+    ) -> Result<Vec<VerifiedChain>, VerifyError> {
+        self.verify_evidence_at(evidence, nonce, user_data, self.clock.now())
+    }
+
+    /// Like `verify_evidence` but each cert must be valid & each CRL current
+    /// at `time`, e.g. the time the evidence was collected when auditing it
+    /// later.
+    pub fn verify_evidence_at(
+        &self,
+        evidence: &Evidence,
+        nonce: &Nonce,
+        user_data: &[u8],
+        time: SystemTime,
+    ) -> Result<Vec<VerifiedChain>, VerifyError> {
+        let cert_chains = self
+            .rot_verifiers
+            .verify_cert_chains(&evidence.cert_chains, time)?;
+        check_validity(&evidence.cert_chains, time)?;
+
+        // Reconstruct the 32 bytes passed from the `AttestationSigner` to
+        // each hardware RoT: `sha256(instance_log | nonce | data)`
+        let instance_log = find_log(&evidence.logs, RotType::OxideInstance)?;
+        let mut data_digest = Sha256::new();
+        data_digest.update(&instance_log.data);
+        data_digest.update(nonce);
+        data_digest.update(user_data);
+        let data_digest: [u8; 32] = data_digest.finalize().into();
+
+        self.rot_verifiers.verify_attestations(
+            &evidence.attestations,
+            &evidence.cert_chains,
+            &evidence.logs,
+            &data_digest,
+        )?;
+
+        Ok(cert_chains)
+    }
+
+    /// Appraise the measurements from `evidence` against the reference
+    /// manifests & instance policy.
+    ///
+    /// NOTE: This does not verify the cert chains or attestations. It should
+    /// only be used on evidence that has passed `verify_evidence`.
+    pub fn appraise(
+        &self,
+        evidence: &Evidence,
+    ) -> Result<AppraisalReport, AppraisalError> {
+        self.rot_verifiers
+            .appraise(&evidence.cert_chains, &evidence.logs)
+    }
+}
+
+/// Check that `time` is within the validity period of every cert in
+/// `cert_chains`.
+fn check_validity(
+    cert_chains: &[CertChain],
+    time: SystemTime,
+) -> Result<(), VerifyError> {
+    for cert_chain in cert_chains {
+        for (index, cert) in cert_chain.pki_path.iter().enumerate() {
+            let validity = &cert.tbs_certificate.validity;
+            if time < validity.not_before.to_system_time() {
+                return Err(VerifyError::CertNotYetValid {
+                    rot: cert_chain.rot,
+                    index,
+                    not_before: validity.not_before,
+                });
+            }
+            if time > validity.not_after.to_system_time() {
+                return Err(VerifyError::CertExpired {
+                    rot: cert_chain.rot,
+                    index,
+                    not_after: validity.not_after,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn find_log(
+    logs: &[MeasurementLog],
+    rot: RotType,
+) -> Result<&MeasurementLog, VerifyError> {
+    logs.iter()
+        .find(|log| log.rot == rot)
+        .ok_or(VerifyError::NoMeasurementLog(rot))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::{
+        config, setup, setup_instance_key, setup_with_pki_path, verifier_config,
+    };
+
+    const USER_DATA: [u8; 32] = [0u8; 32];
+
+    #[test]
+    fn config_from_kdl() {
+        let kdl = format!(
+            r#"
+            trust-anchor "{}"
+            corim "{}"
+            instance-policy "{}"
+            freshness-window 60
+            nonce-store "memory"
+            "#,
+            config::PKI_ROOT,
+            config::CORIM,
+            config::INSTANCE_POLICY,
+        );
+        let config = VerifierConfig::from_kdl("test", &kdl)
+            .expect("VerifierConfig from KDL");
+        assert_eq!(config, verifier_config());
+    }
+
+    #[test]
+    fn config_from_json() {
+        let json = format!(
+            r#"{{
+                "trust-anchors": [ "{}" ],
+                "corims": [ "{}" ],
+                "instance-policy": "{}",
+                "freshness-window": 60
+            }}"#,
+            config::PKI_ROOT,
+            config::CORIM,
+            config::INSTANCE_POLICY,
+        );
+        let config =
+            VerifierConfig::from_json(&json).expect("VerifierConfig from JSON");
+        assert_eq!(config, verifier_config());
+    }
+
+    #[test]
+    fn config_no_freshness_window() {
+        let result =
+            VerifierConfig::from_kdl("test", r#"trust-anchor "root.pem""#);
+        assert!(matches!(
+            result,
+            Err(VerifierConfigError::NoFreshnessWindow)
+        ));
+
+        let result =
+            VerifierConfig::from_json(r#"{ "trust-anchors": [ "root.pem" ] }"#);
+        assert!(matches!(result, Err(VerifierConfigError::Json(_))));
+    }
+
+    #[test]
+    fn config_staged_anchors_only() {
+        // a trust anchor dir w/ the platform root staged
+        let dir = std::env::temp_dir()
+            .join(format!("verifier-staged-only-{}", std::process::id()));
+        let staged = dir.join(crate::trust_store::STAGED_DIR);
+        fs::create_dir_all(&staged).expect("create trust dir");
+        fs::copy(config::PKI_ROOT, staged.join("next.pem"))
+            .expect("copy platform root");
+
+        let result = Verifier::from_config(&VerifierConfig {
+            trust_anchors: vec![dir.clone()],
+            ..verifier_config()
+        });
+        let _ = fs::remove_dir_all(&dir);
+        assert!(matches!(result, Err(VerifierConfigError::NoTrustAnchors)));
+    }
+
+    #[test]
+    fn config_relative_paths() {
+        let config = VerifierConfig::from_kdl(
+            "test",
+            r#"
+            trust-anchor "root.pem"
+            trust-anchor "/abs/root.pem"
+            freshness-window 60
+            nonce-store "file" path="nonces.json"
+            "#,
+        )
+        .expect("VerifierConfig from KDL")
+        .relative_to(Path::new("/etc/verifier"));
+
+        assert_eq!(
+            config.trust_anchors,
+            vec![
+                PathBuf::from("/etc/verifier/root.pem"),
+                PathBuf::from("/abs/root.pem")
+            ]
+        );
+        assert_eq!(
+            config.nonce_store,
+            NonceStoreConfig::File {
+                path: "/etc/verifier/nonces.json".into()
+            }
+        );
+    }
+
+    #[test]
+    fn verify() {
+        let attest = setup();
+        let verifier = Verifier::from_config(&verifier_config())
+            .expect("Verifier from config");
+
+        let nonce = verifier.issue_nonce().expect("issue nonce");
+        let evidence =
+            Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+
+        let report = verifier
+            .verify(&evidence, &nonce, &USER_DATA)
+            .expect("verify evidence");
+        assert!(report.is_ok(), "{report}");
+        // anchors loaded from a file are named by its path
+        assert!(
+            report
+                .cert_chains
+                .iter()
+                .all(|c| c.trust_anchor == config::PKI_ROOT)
+        );
+        assert_eq!(report.cert_chains.len(), 2);
+        // both hardware RoTs are on the platform from test-data/config.kdl
+        assert!(report.cert_chains.iter().all(|c| {
+            c.platform
+                .as_ref()
+                .is_some_and(|p| p.serial == "00000000001")
+        }));
+        assert_eq!(report.platform().map(|p| p.alias.as_str()), Some("alias"));
+
+        // nonces can't be reused
+        let result = verifier.verify(&evidence, &nonce, &USER_DATA);
+        assert!(matches!(result, Err(VerifyError::UnknownNonce)));
+    }
+
+    #[test]
+    fn verify_offline() {
+        let attest = setup();
+        let verifier = Verifier::from_config(&verifier_config())
+            .expect("Verifier from config");
+
+        // saved evidence is verified w/o a nonce issued by this verifier
+        let nonce = Nonce::from_array([1u8; 32]);
+        let evidence =
+            Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+
+        verifier
+            .verify_evidence(&evidence, &nonce, &USER_DATA)
+            .expect("verify evidence offline");
+        let report = verifier.appraise(&evidence).expect("appraise evidence");
+        assert!(report.is_ok(), "{report}");
+
+        let result = verifier.verify_evidence(
+            &evidence,
+            &Nonce::from_array([2u8; 32]),
+            &USER_DATA,
+        );
+        assert!(matches!(result, Err(VerifyError::Attestation(_))));
+    }
+
+    #[test]
+    fn verify_expired_cert() {
+        let attest = setup_with_pki_path(config::EXPIRED_PKIPATH);
+        let verifier = Verifier::from_config(&verifier_config())
+            .expect("Verifier from config");
+
+        let nonce = verifier.issue_nonce().expect("issue nonce");
+        let evidence =
+            Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+
+        let result = verifier.verify(&evidence, &nonce, &USER_DATA);
+        assert!(matches!(
+            result,
+            Err(VerifyError::CertExpired {
+                rot: RotType::OxideHardware,
+                index: 0,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn verify_not_yet_valid_cert() {
+        let attest = setup_with_pki_path(config::NOT_YET_VALID_PKIPATH);
+        let verifier = Verifier::from_config(&verifier_config())
+            .expect("Verifier from config");
+
+        let nonce = verifier.issue_nonce().expect("issue nonce");
+        let evidence =
+            Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+
+        let result = verifier.verify(&evidence, &nonce, &USER_DATA);
+        assert!(matches!(
+            result,
+            Err(VerifyError::CertNotYetValid {
+                rot: RotType::OxideHardware,
+                index: 0,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn verify_pki_policy() {
+        let attest = setup_with_pki_path(config::NO_POLICY_PKIPATH);
+        let verifier = Verifier::from_config(&verifier_config())
+            .expect("Verifier from config");
+
+        let nonce = verifier.issue_nonce().expect("issue nonce");
+        let evidence =
+            Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+
+        let result = verifier.verify(&evidence, &nonce, &USER_DATA);
+        assert!(matches!(
+            result,
+            Err(VerifyError::PkiPolicy(
+                RotType::OxideHardware,
+                PkiPolicyError::Policy { index: 0, .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn verify_revoked_cert() {
+        let attest = setup();
+        let verifier = Verifier::from_config(&VerifierConfig {
+            crls: vec![config::CRL.into()],
+            ..verifier_config()
+        })
+        .expect("Verifier from config");
+
+        let nonce = verifier.issue_nonce().expect("issue nonce");
+        let evidence =
+            Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+
+        let result = verifier.verify(&evidence, &nonce, &USER_DATA);
+        assert!(matches!(
+            result,
+            Err(VerifyError::Revocation(
+                RotType::OxideHardware,
+                RevocationError::Revoked { index: 0, .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn verify_evidence_at() {
+        let nonce = Nonce::from_array([1u8; 32]);
+        let evidence = Evidence::collect(
+            &setup_with_pki_path(config::EXPIRED_PKIPATH),
+            &nonce,
+            &USER_DATA,
+        )
+        .expect("evidence");
+
+        // the expired cert was valid during 2000
+        let collected = der::DateTime::new(2000, 6, 1, 0, 0, 0)
+            .expect("DateTime")
+            .to_system_time();
+        let verifier = Verifier::from_config(&verifier_config())
+            .expect("Verifier from config")
+            .with_clock(Arc::new(FixedClock(collected)));
+        verifier
+            .verify_evidence(&evidence, &nonce, &USER_DATA)
+            .expect("verify evidence w/ fixed clock");
+        verifier
+            .verify_evidence_at(
+                &evidence,
+                &nonce,
+                &USER_DATA,
+                SystemTime::now(),
+            )
+            .expect_err("verify expired evidence at the current time");
+
+        // no cert in the chain was valid in 1970
+        let result = verifier.verify_evidence_at(
+            &evidence,
+            &nonce,
+            &USER_DATA,
+            SystemTime::UNIX_EPOCH,
+        );
+        assert!(matches!(
+            result,
+            Err(VerifyError::CertNotYetValid { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn verify_evidence_json() {
+        let attest = setup_instance_key();
+        let verifier = Verifier::from_config(&verifier_config())
+            .expect("Verifier from config");
+
+        let nonce = verifier.issue_nonce().expect("issue nonce");
+        let evidence =
+            Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+        let json = serde_json::to_string(&evidence).expect("evidence to JSON");
+        let evidence: Evidence =
+            serde_json::from_str(&json).expect("evidence from JSON");
+
+        let report = verifier
+            .verify(&evidence, &nonce, &USER_DATA)
+            .expect("verify evidence from JSON");
+        assert!(report.is_ok(), "{report}");
+    }
+
+    #[test]
+    fn verify_duplicates() {
+        let attest = setup();
+        let verifier = Verifier::from_config(&verifier_config())
+            .expect("Verifier from config");
+
+        // a second cert chain from the SP RoT
+        let nonce = verifier.issue_nonce().expect("issue nonce");
+        let mut evidence =
+            Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+        let cert_chains = attest.get_cert_chains().expect("get_cert_chains");
+        evidence.cert_chains.extend(
+            cert_chains
+                .into_iter()
+                .filter(|c| c.rot == RotType::OxideSp),
+        );
+        let result = verifier.verify(&evidence, &nonce, &USER_DATA);
+        assert!(matches!(
+            result,
+            Err(VerifyError::DuplicateCertChain(RotType::OxideSp))
+        ));
+
+        // a second log from the SP RoT, it would reach the appraisal w/o
+        // being covered by an attestation
+        let nonce = verifier.issue_nonce().expect("issue nonce");
+        let mut evidence =
+            Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+        let logs = attest.get_measurement_logs().expect("get_measurement_logs");
+        evidence
+            .logs
+            .extend(logs.into_iter().filter(|l| l.rot == RotType::OxideSp));
+        let result = verifier.verify(&evidence, &nonce, &USER_DATA);
+        assert!(matches!(
+            result,
+            Err(VerifyError::DuplicateLog(RotType::OxideSp))
+        ));
+    }
+
+    #[test]
+    fn verify_stale_nonce() {
+        let attest = setup();
+        let verifier = Verifier::from_config(&verifier_config())
+            .expect("Verifier from config");
+
+        // record a nonce as though it was issued before the freshness window
+        let nonce = Nonce::from_platform_rng().expect("Nonce from RNG");
+        let issued = SystemTime::now() - Duration::from_secs(120);
+        verifier
+            .nonce_store
+            .insert(&nonce, issued)
+            .expect("insert nonce");
+
+        let evidence =
+            Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+        let result = verifier.verify(&evidence, &nonce, &USER_DATA);
+        assert!(matches!(result, Err(VerifyError::StaleNonce(_))));
+    }
+
+    #[test]
+    fn verify_every_attestation() {
+        let attest = setup();
+        let verifier = Verifier::from_config(&verifier_config())
+            .expect("Verifier from config");
+
+        // drop the attestation from the SP RoT
+        let nonce = verifier.issue_nonce().expect("issue nonce");
+        let mut evidence =
+            Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+        evidence.attestations.retain(|a| a.rot != RotType::OxideSp);
+        let result = verifier.verify(&evidence, &nonce, &USER_DATA);
+        assert!(matches!(
+            result,
+            Err(VerifyError::NoAttestation(RotType::OxideSp))
+        ));
+
+        // swap the order of the attestations
+        let nonce = verifier.issue_nonce().expect("issue nonce");
+        let mut evidence =
+            Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+        evidence.attestations.reverse();
+        let result = verifier.verify(&evidence, &nonce, &USER_DATA);
+        assert!(matches!(result, Err(VerifyError::AttestationOrder)));
+    }
+
+    #[test]
+    fn verify_instance_key() {
+        let attest = setup_instance_key();
+        let verifier = Verifier::from_config(&verifier_config())
+            .expect("Verifier from config");
+
+        let nonce = verifier.issue_nonce().expect("issue nonce");
+        let evidence =
+            Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+        let report = verifier
+            .verify(&evidence, &nonce, &USER_DATA)
+            .expect("verify evidence");
+        assert!(report.is_ok(), "{report}");
+
+        // w/o the attestation from the instance key nothing endorses the
+        // cert chain from the Oxide platform RoT
+        let nonce = verifier.issue_nonce().expect("issue nonce");
+        let mut evidence =
+            Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+        evidence
+            .attestations
+            .retain(|a| a.rot != RotType::OxideInstance);
+        let result = verifier.verify(&evidence, &nonce, &USER_DATA);
+        assert!(matches!(
+            result,
+            Err(VerifyError::NoAttestation(RotType::OxideHardware))
+        ));
+
+        // the instance cert binds the log from the Oxide platform RoT, a
+        // substituted log doesn't match it
+        let nonce = verifier.issue_nonce().expect("issue nonce");
+        let mut evidence =
+            Evidence::collect(&attest, &nonce, &USER_DATA).expect("evidence");
+        let sp_log = evidence
+            .logs
+            .iter()
+            .find(|l| l.rot == RotType::OxideSp)
+            .expect("log from RotType::OxideSp")
+            .data
+            .clone();
+        for log in &mut evidence.logs {
+            if log.rot == RotType::OxideHardware {
+                log.data = sp_log.clone();
+            }
+        }
+        let result = verifier.verify(&evidence, &nonce, &USER_DATA);
+        assert!(matches!(
+            result,
+            Err(VerifyError::InstanceKey(InstanceKeyError::PlatformLog))
+        ));
+    }
+
+    #[test]
+    fn verify_batch() {
+        let attest = setup();
+        let verifier = Verifier::from_config(&verifier_config())
+            .expect("Verifier from config");
+
+        let nonces: Vec<_> = (0..3)
+            .map(|_| verifier.issue_nonce().expect("issue nonce"))
+            .collect();
+        let challenges: Vec<_> =
+            nonces.iter().map(|nonce| (nonce, &USER_DATA[..])).collect();
+        let mut batch = attest.attest_batch(&challenges).expect("attest batch");
+        assert_eq!(batch.len(), nonces.len());
+
+        let evidence = |attestations| Evidence {
+            attestations,
+            logs: attest.get_measurement_logs().expect("get_measurement_logs"),
+            cert_chains: attest.get_cert_chains().expect("get_cert_chains"),
+        };
+
+        // a proof for another caller's transcript doesn't verify
+        let result = verifier.verify(
+            &evidence(batch[1].clone()),
+            &nonces[0],
+            &USER_DATA,
+        );
+        assert!(matches!(result, Err(VerifyError::Attestation(_))));
+
+        // nonce 0 was consumed by the failed attempt
+        for (nonce, attestations) in nonces.iter().zip(batch.drain(..)).skip(1)
+        {
+            let report = verifier
+                .verify(&evidence(attestations), nonce, &USER_DATA)
+                .expect("verify batched evidence");
+            assert!(report.is_ok(), "{report}");
+        }
+    }
+}
@@ -0,0 +1,141 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Run the `vm-attest` CLI against the mock RoTs & test data generated by
+//! build.rs.
+
+use serde_json::{Value, json};
+use std::{
+    fs,
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+/// This module holds `const &str`s with paths to test data generated by
+/// build.rs
+mod config {
+    include!(concat!(env!("OUT_DIR"), "/config.rs"));
+}
+
+const NONCE: &str =
+    "0000000000000000000000000000000000000000000000000000000000000000";
+
+fn vm_attest(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_vm-attest"))
+        .args(args)
+        .output()
+        .expect("run vm-attest")
+}
+
+fn stdout_json(output: &Output) -> Value {
+    serde_json::from_slice(&output.stdout).expect("parse JSON from stdout")
+}
+
+/// Write a config for the mock RoTs & collect an evidence bundle from them,
+/// returning the path to the bundle.
+fn collect_bundle(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("vm-attest-cli-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).expect("create temp dir");
+
+    let mock = json!({
+        "oxide-hardware": {
+            "pki-path": config::SIGNER_PKIPATH,
+            "log": config::LOG,
+            "signing-key": config::ATTESTATION_SIGNER,
+        },
+        "oxide-sp": {
+            "pki-path": config::SP_SIGNER_PKIPATH,
+            "log": config::SP_LOG,
+            "signing-key": config::SP_ATTESTATION_SIGNER,
+        },
+        "instance-cfg": config::VM_INSTANCE_CFG,
+    });
+    let mock_path = dir.join("mock.json");
+    fs::write(&mock_path, mock.to_string()).expect("write mock config");
+
+    let bundle = dir.join("evidence.json");
+    let output = vm_attest(&[
+        "--mock",
+        mock_path.to_str().expect("mock config path"),
+        "evidence",
+        "--nonce",
+        NONCE,
+        "--out",
+        bundle.to_str().expect("bundle path"),
+    ]);
+    assert!(output.status.success(), "{output:?}");
+
+    bundle
+}
+
+#[test]
+fn verify_appraise() {
+    let bundle = collect_bundle("verify-appraise");
+    let bundle_path = bundle.to_str().expect("bundle path");
+
+    let output = vm_attest(&[
+        "verify",
+        bundle_path,
+        "--root",
+        config::PKI_ROOT,
+        "--json",
+    ]);
+    assert!(output.status.success(), "{output:?}");
+    let outcome = stdout_json(&output);
+    assert_eq!(outcome["passed"], true);
+    assert!(outcome.get("error").is_none());
+    assert!(outcome.get("appraisal").is_none());
+
+    let output = vm_attest(&[
+        "appraise",
+        bundle_path,
+        "--root",
+        config::PKI_ROOT,
+        "--corim",
+        config::CORIM,
+        "--instance-policy",
+        config::INSTANCE_POLICY,
+        "--json",
+    ]);
+    assert!(output.status.success(), "{output:?}");
+    let outcome = stdout_json(&output);
+    assert_eq!(outcome["passed"], true);
+    assert!(outcome["appraisal"].is_object());
+
+    // the foreign PKI didn't issue the cert chains in the bundle
+    let output = vm_attest(&[
+        "verify",
+        bundle_path,
+        "--root",
+        config::FOREIGN_PKIPATH,
+        "--json",
+    ]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let outcome = stdout_json(&output);
+    assert_eq!(outcome["passed"], false);
+    assert!(outcome["error"].is_array());
+
+    fs::remove_dir_all(bundle.parent().expect("bundle dir"))
+        .expect("remove temp dir");
+}
+
+#[test]
+fn appraise_requires_instance_policy() {
+    let bundle = collect_bundle("no-policy");
+    let output = vm_attest(&[
+        "appraise",
+        bundle.to_str().expect("bundle path"),
+        "--root",
+        config::PKI_ROOT,
+        "--corim",
+        config::CORIM,
+        "--json",
+    ]);
+    assert!(!output.status.success(), "{output:?}");
+    assert!(output.stdout.is_empty());
+
+    fs::remove_dir_all(bundle.parent().expect("bundle dir"))
+        .expect("remove temp dir");
+}